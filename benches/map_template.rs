@@ -0,0 +1,93 @@
+//! Measures how long it takes to get a playable layer for a new match, comparing loading the map
+//! from its region files with copying it from an in-memory template. `anvil` is the path games
+//! took before templates, where `init_world` loaded the map again for every game.
+//!
+//! Run with `cargo bench --bench map_template` from the repository root.
+
+use chunkedge::anvil::AnvilLevel;
+use chunkedge::network::NetworkPlugin;
+use chunkedge::prelude::*;
+use criterion::{Criterion, criterion_group, criterion_main};
+use minibit_lib::config::WorldValue;
+use minibit_lib::duels::DefaultDuelsConfig;
+use minibit_lib::duels::copied_map::MapTemplate;
+use std::path::Path;
+
+const DATA_PATH: &str = "data/bridge";
+
+fn setup() -> (App, WorldValue) {
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins.build().disable::<NetworkPlugin>());
+    app.update();
+
+    let config = std::fs::read_to_string(Path::new(DATA_PATH).join("config.json")).unwrap();
+    let mut config: DefaultDuelsConfig = serde_json::from_str(&config).unwrap();
+    (app, config.worlds.remove(1))
+}
+
+fn start_match(c: &mut Criterion) {
+    let (mut app, world) = setup();
+    let template = MapTemplate::load(
+        &world,
+        Path::new(DATA_PATH),
+        app.world().resource::<BiomeRegistry>(),
+    )
+    .unwrap();
+
+    let mut group = c.benchmark_group("start_match");
+    group.sample_size(10);
+
+    group.bench_function("anvil", |b| {
+        b.iter(|| {
+            let world_ref = app.world();
+            let layer = LayerBundle::new(
+                ident!("overworld"),
+                world_ref.resource::<DimensionTypeRegistry>(),
+                world_ref.resource::<BiomeRegistry>(),
+                world_ref.resource::<Server>(),
+            );
+            let mut level = AnvilLevel::new(
+                Path::new(DATA_PATH).join(&world.path),
+                world_ref.resource::<BiomeRegistry>(),
+            );
+            for z in world.z_chunks[0]..=world.z_chunks[1] {
+                for x in world.x_chunks[0]..=world.x_chunks[1] {
+                    let pos = ChunkPos::new(x, z);
+                    level.ignored_chunks.insert(pos);
+                    level.force_chunk_load(pos);
+                }
+            }
+            let layer = app.world_mut().spawn((layer, level)).id();
+
+            // Chunks are loaded on other threads, so the match can only start once all of them
+            // have been inserted
+            loop {
+                app.update();
+                let chunks = app.world().get::<ChunkLayer>(layer).unwrap().chunks().count();
+                if chunks >= template.chunk_count() {
+                    break;
+                }
+            }
+            app.world_mut().despawn(layer);
+        })
+    });
+
+    group.bench_function("template", |b| {
+        b.iter(|| {
+            let world_ref = app.world();
+            let layer = template.instantiate(
+                world_ref.resource::<Server>(),
+                world_ref.resource::<DimensionTypeRegistry>(),
+                world_ref.resource::<BiomeRegistry>(),
+            );
+            let layer = app.world_mut().spawn(layer).id();
+            app.update();
+            app.world_mut().despawn(layer);
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, start_match);
+criterion_main!(benches);
@@ -1,7 +1,11 @@
 #!/bin/bash
 
 SECRET=$(LC_ALL=C tr -dc 'A-Za-z0-9' < /dev/urandom | head -c 12)
+DB_PASSWORD=$(LC_ALL=C tr -dc 'A-Za-z0-9' < /dev/urandom | head -c 16)
 cat << EOF > .env.compose
 MINIBIT_FORWARDING_SECRET=$SECRET
 VELOCITY_FORWARDING_SECRET=$SECRET
+POSTGRES_USER=minibit
+POSTGRES_PASSWORD=$DB_PASSWORD
+MINIBIT_DATABASE_URL=postgres://minibit:$DB_PASSWORD@db/minibit
 EOF
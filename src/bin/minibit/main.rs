@@ -50,6 +50,8 @@ struct ServerConfig {
     enabled: bool,
     path: PathBuf,
     network: NetworkConfig,
+    #[serde(skip)]
+    database_url: String,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -68,6 +70,12 @@ impl Default for ForwardingConfig {
     }
 }
 
+#[derive(Default, Deserialize, Serialize, Clone)]
+#[serde(default)]
+struct DatabaseConfig {
+    url: String,
+}
+
 #[rustfmt::skip]
 #[derive(Args, Default, Deserialize, Serialize)]
 #[serde(default)]
@@ -76,12 +84,14 @@ struct Config {
     data_path: PathBuf,
 
     #[clap(skip)] forwarding: ForwardingConfig,
+    #[clap(skip)] database: DatabaseConfig,
 
     #[clap(skip)] lobby: ServerConfig,
     #[clap(skip)] bowfight: ServerConfig,
     #[clap(skip)] boxing: ServerConfig,
     #[clap(skip)] bridge: ServerConfig,
     #[clap(skip)] classic: ServerConfig,
+    #[clap(skip)] modern: ServerConfig,
     #[clap(skip)] parkour: ServerConfig,
     #[clap(skip)] spaceshooter: ServerConfig,
     #[clap(skip)] sumo: ServerConfig,
@@ -124,6 +134,7 @@ fn main() {
         subserver!(boxing, config),
         subserver!(bridge, config),
         subserver!(classic, config),
+        subserver!(modern, config),
         subserver!(parkour, config),
         subserver!(spaceshooter, config),
         subserver!(sumo, config),
@@ -141,6 +152,7 @@ fn main() {
         cloned_config.path = config.data_path.join(cloned_config.path);
         cloned_config.network.forwarding_secret = config.forwarding.secret.clone();
         cloned_config.network.connection_mode = config.forwarding.mode;
+        cloned_config.database_url = config.database.url.clone();
 
         println!("Starting server {}", server);
         handles.push(thread::spawn(move || {
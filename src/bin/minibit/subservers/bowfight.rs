@@ -1,21 +1,13 @@
 #![allow(clippy::type_complexity)]
 
-use std::marker::PhantomData;
-use bevy_ecs::query::QueryData;
+use minibit_lib::combat::{CombatPlugin, DamageMessage, KnockbackProfile, MeleeDamage};
 use minibit_lib::duels::*;
+use minibit_lib::duels::time_limit::TimeLimitOutcome;
 use minibit_lib::player::InteractionBroadcastPlugin;
 use minibit_lib::projectiles::*;
-use chunkedge::entity::living::Health;
 use chunkedge::entity::Velocity;
-use chunkedge::entity::{EntityId, EntityStatuses};
 use chunkedge::equipment::EquipmentInventorySync;
-use chunkedge::math::Vec3Swizzles;
 use chunkedge::prelude::*;
-use chunkedge::protocol::packets::play::HurtAnimationS2c;
-use chunkedge::protocol::sound::SoundCategory;
-use chunkedge::protocol::Sound;
-use chunkedge::protocol::VarInt;
-use chunkedge::protocol::WritePacket;
 use crate::ServerConfig;
 
 pub fn main(config: ServerConfig) {
@@ -23,21 +15,34 @@ pub fn main(config: ServerConfig) {
         .add_plugins(DuelsPlugin::<DefaultDuelsConfig> {
             path: config.path,
             network_config: config.network,
+            database_url: config.database_url,
+            minigame: "bowfight",
             default_gamemode: GameMode::Adventure,
             copy_map: false,
-            phantom: PhantomData
+            time_limit_secs: 180,
+            time_limit_outcome: TimeLimitOutcome::MostHealth,
+            ..Default::default()
         })
         .add_plugins(DefaultPlugins)
-        .add_plugins((InteractionBroadcastPlugin, ProjectilePlugin))
-        .add_systems(
-            EventLoopUpdate,
-            handle_combat_events,
-        )
+        .add_plugins((
+            InteractionBroadcastPlugin,
+            ProjectilePlugin {
+                arrow_pickup: true,
+            },
+        ))
+        .add_plugins(CombatPlugin {
+            melee_damage: MeleeDamage::Fixed(1.0),
+            // Bowfight always carried the victim's last movement over into the knockback
+            knockback: KnockbackProfile {
+                friction: Some(20.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
         .add_systems(
             Update,
             (
                 init_clients,
-                gamestage_change.after(gameloop::<DefaultDuelsConfig>),
                 end_game.after(map::end_game::<DefaultDuelsConfig>),
                 handle_collision_events,
                 handle_oob_clients,
@@ -52,26 +57,6 @@ fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
     }
 }
 
-fn gamestage_change(
-    mut clients: Query<&mut Inventory, With<Client>>,
-    games: Query<&Entities>,
-    mut messages: MessageReader<GameStageMessage>,
-) {
-    for message in messages.read() {
-        if message.stage != 4 {
-            continue;
-        }
-        if let Ok(entities) = games.get(message.game_id) {
-            for entity in entities.0.iter() {
-                if let Ok(mut inventory) = clients.get_mut(*entity) {
-                    inventory.set_slot(36, ItemStack::new(ItemKind::Bow, 1));
-                    inventory.set_slot(44, ItemStack::new(ItemKind::Arrow, 10));
-                }
-            }
-        }
-    }
-}
-
 fn end_game(
     mut clients: Query<&mut Inventory, With<Client>>,
     games: Query<&Entities>,
@@ -90,173 +75,35 @@ fn end_game(
     }
 }
 
-#[derive(QueryData)]
-#[query_data(mutable)]
-struct CombatQuery {
-    client: &'static mut Client,
-    id: &'static EntityId,
-    pos: &'static Position,
-    old_pos: &'static OldPosition,
-    state: &'static mut CombatState,
-    statuses: &'static mut EntityStatuses,
-    gamestate: &'static PlayerGameState,
-    health: &'static mut Health,
-}
-
-fn handle_combat_events(
-    server: Res<Server>,
-    mut clients: Query<CombatQuery>,
-    mut sprinting: MessageReader<SprintMessage>,
-    mut interact_entity: MessageReader<InteractEntityMessage>,
-    mut end_game: MessageWriter<EndGameMessage>,
-) {
-    for &SprintMessage { client, state } in sprinting.read() {
-        if let Ok(mut client) = clients.get_mut(client) {
-            client.state.has_bonus_knockback = state == SprintState::Start;
-        }
-    }
-
-    for &InteractEntityMessage {
-        client: attacker_client,
-        entity: victim_client,
-        interact: interaction,
-        ..
-    } in interact_entity.read()
-    {
-        let Ok([mut attacker, mut victim]) = clients.get_many_mut([attacker_client, victim_client])
-        else {
-            continue;
-        };
-
-        if interaction != EntityInteraction::Attack
-            || server.current_tick() - victim.state.last_attacked_tick < 10
-            || attacker.gamestate.game_id != victim.gamestate.game_id
-        {
-            continue;
-        }
-
-        victim.state.last_attacked_tick = server.current_tick();
-
-        let victim_pos = victim.pos.0.xz();
-        let attacker_pos = attacker.pos.0.xz();
-
-        let dir = (victim_pos - attacker_pos).normalize().as_vec2();
-
-        let knockback_xz = if attacker.state.has_bonus_knockback {
-            18.0
-        } else {
-            8.0
-        };
-        let knockback_y = if attacker.state.has_bonus_knockback {
-            8.432
-        } else {
-            6.432
-        };
-
-        damage_player(
-            &mut attacker,
-            &mut victim,
-            1.0,
-            Vec3::new(dir.x * knockback_xz, knockback_y, dir.y * knockback_xz),
-            &mut end_game,
-        );
-
-        attacker.state.has_bonus_knockback = false;
-    }
-}
-
 fn handle_collision_events(
-    mut clients: Query<CombatQuery>,
     arrows: Query<(&Velocity, &ProjectileOwner)>,
     mut collisions: MessageReader<ProjectileCollisionMessage>,
-    mut end_game: MessageWriter<EndGameMessage>,
+    mut damage: MessageWriter<DamageMessage>,
 ) {
     for message in collisions.read() {
-        if let Ok((vel, owner)) = arrows.get(message.arrow)
-            && let Ok([mut attacker, mut victim]) = clients.get_many_mut([owner.0, message.player])
-        {
-            damage_player(
-                &mut attacker,
-                &mut victim,
-                (0.13 * vel.0.length()) as f32,
-                Vec3::new(0.0, 0.0, 0.0),
-                &mut end_game,
-            );
-            attacker.client.play_sound(
-                Sound::EntityArrowHitPlayer,
-                SoundCategory::Player,
-                attacker.pos.0,
-                1.0,
-                1.0,
-            );
+        if let Ok((vel, owner)) = arrows.get(message.arrow) {
+            damage.write(DamageMessage {
+                attacker: owner.0,
+                victim: message.player,
+                damage: (0.13 * vel.0.length()) as f32,
+                knockback: Vec3::ZERO,
+                projectile: true,
+                critical: false,
+            });
         }
     }
 }
 
 fn handle_oob_clients(
-    positions: Query<(&mut Position, &PlayerGameState), With<Client>>,
-    mut end_game: MessageWriter<EndGameMessage>,
+    positions: Query<(Entity, &Position, &PlayerGameState), With<Client>>,
+    mut eliminations: MessageWriter<EliminationMessage>,
 ) {
-    for (pos, gamestate) in positions.iter() {
-        if pos.0.y < 0.0 && let Some(game_id) = gamestate.game_id {
-            end_game.write(EndGameMessage {
+    for (entity, pos, gamestate) in positions.iter() {
+        if pos.0.y < 0.0 && !gamestate.eliminated && let Some(game_id) = gamestate.game_id {
+            eliminations.write(EliminationMessage {
                 game_id,
-                loser: gamestate.team,
+                player: entity,
             });
         }
     }
 }
-
-// Helper functions below
-
-fn damage_player(
-    attacker: &mut CombatQueryItem,
-    victim: &mut CombatQueryItem,
-    damage: f32,
-    velocity: Vec3,
-    end_game: &mut MessageWriter<EndGameMessage>,
-) {
-    let old_vel = Vec3::new(
-        (victim.pos.0.x - victim.old_pos.get().x) as f32,
-        (victim.pos.0.y - victim.old_pos.get().y) as f32,
-        (victim.pos.0.z - victim.old_pos.get().z) as f32,
-    );
-
-    victim
-        .client
-        .set_velocity(old_vel + velocity);
-
-    attacker.state.has_bonus_knockback = false;
-
-    victim.client.play_sound(
-        Sound::EntityPlayerHurt,
-        SoundCategory::Player,
-        victim.pos.0,
-        1.0,
-        1.0,
-    );
-    victim.client.write_packet(&HurtAnimationS2c {
-        entity_id: VarInt(0),
-        yaw: 0.0,
-    });
-    attacker.client.play_sound(
-        Sound::EntityPlayerHurt,
-        SoundCategory::Player,
-        victim.pos.0,
-        1.0,
-        1.0,
-    );
-    attacker.client.write_packet(&HurtAnimationS2c {
-        entity_id: VarInt(victim.id.get()),
-        yaw: 0.0,
-    });
-
-    if victim.health.0 <= damage {
-        end_game.write(EndGameMessage {
-            game_id: victim.gamestate.game_id.unwrap(),
-            loser: victim.gamestate.team,
-        });
-    } else {
-        victim.health.0 -= damage;
-    }
-}
\ No newline at end of file
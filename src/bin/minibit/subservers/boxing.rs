@@ -1,16 +1,11 @@
 #![allow(clippy::type_complexity)]
 
-use std::marker::PhantomData;
-use bevy_ecs::query::QueryData;
-use minibit_lib::duels::{CombatState, DefaultDuelsConfig, DuelsPlugin, EndGameMessage, Entities, PlayerGameState};
-use chunkedge::entity::{EntityId, EntityStatuses};
-use chunkedge::math::Vec3Swizzles;
+use minibit_lib::combat::{CombatPlugin, CombatSet, MeleeDamage};
+use minibit_lib::duels::{DefaultDuelsConfig, DuelsPlugin, EliminationMessage, Entities, PlayerGameState};
+use minibit_lib::duels::time_limit::TimeLimitOutcome;
+use minibit_lib::duels::stats::HitMessage;
+use minibit_lib::duels::rounds::RoundStartMessage;
 use chunkedge::prelude::*;
-use chunkedge::protocol::packets::play::HurtAnimationS2c;
-use chunkedge::protocol::sound::SoundCategory;
-use chunkedge::protocol::Sound;
-use chunkedge::protocol::VarInt;
-use chunkedge::protocol::WritePacket;
 use crate::ServerConfig;
 
 #[derive(Component, Default)]
@@ -23,17 +18,26 @@ pub fn main(config: ServerConfig) {
         .add_plugins(DuelsPlugin::<DefaultDuelsConfig> {
             path: config.path,
             network_config: config.network,
+            database_url: config.database_url,
+            minigame: "boxing",
             default_gamemode: GameMode::Adventure,
             copy_map: false,
-            phantom: PhantomData
+            rounds_to_win: 2,
+            time_limit_secs: 120,
+            time_limit_outcome: TimeLimitOutcome::Draw,
+            ..Default::default()
         })
         .add_plugins(DefaultPlugins)
-        .add_systems(EventLoopUpdate, handle_combat_events)
+        .add_plugins(CombatPlugin {
+            melee_damage: MeleeDamage::None,
+            ..Default::default()
+        })
         .add_systems(
             Update,
             (
                 init_clients.after(minibit_lib::duels::map::init_clients::<DefaultDuelsConfig>),
-                end_game.after(minibit_lib::duels::map::end_game::<DefaultDuelsConfig>),
+                start_round.after(minibit_lib::duels::rounds::start_round),
+                count_hits.after(CombatSet),
             ),
         )
         .run();
@@ -45,120 +49,38 @@ fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
     }
 }
 
-#[derive(QueryData)]
-#[query_data(mutable)]
-struct CombatQuery {
-    client: &'static mut Client,
-    id: &'static EntityId,
-    pos: &'static Position,
-    state: &'static mut CombatState,
-    statuses: &'static mut EntityStatuses,
-    gamestate: &'static PlayerGameState,
-    boxing_state: &'static mut BoxingState,
-}
-
-fn handle_combat_events(
-    server: Res<Server>,
-    mut clients: Query<CombatQuery>,
-    mut sprinting: MessageReader<SprintMessage>,
-    mut interact_entity: MessageReader<InteractEntityMessage>,
-    mut end_game: MessageWriter<EndGameMessage>,
+fn count_hits(
+    mut clients: Query<(&mut Client, &PlayerGameState, &mut BoxingState)>,
+    mut hits: MessageReader<HitMessage>,
+    mut eliminations: MessageWriter<EliminationMessage>,
 ) {
-    for &SprintMessage { client, state } in sprinting.read() {
-        if let Ok(mut client) = clients.get_mut(client) {
-            client.state.has_bonus_knockback = state == SprintState::Start;
-        }
-    }
-
-    for &InteractEntityMessage {
-        client: attacker_client,
-        entity: victim_client,
-        interact: interaction,
-        ..
-    } in interact_entity.read()
-    {
-        let Ok([mut attacker, mut victim]) = clients.get_many_mut([attacker_client, victim_client])
-        else {
+    for hit in hits.read() {
+        let Ok((mut victim, gamestate, mut boxing_state)) = clients.get_mut(hit.victim) else {
             continue;
         };
+        boxing_state.hits += 1;
 
-        if interaction != EntityInteraction::Attack
-            || server.current_tick() - victim.state.last_attacked_tick < 10
-            || attacker.gamestate.game_id != victim.gamestate.game_id
+        if boxing_state.hits >= 5
+            && let Some(game_id) = gamestate.game_id
         {
-            continue;
-        }
-
-        victim.state.last_attacked_tick = server.current_tick();
-
-        let victim_pos = victim.pos.0.xz();
-        let attacker_pos = attacker.pos.0.xz();
-
-        let dir = (victim_pos - attacker_pos).normalize();
-
-        let knockback_xz = if attacker.state.has_bonus_knockback {
-            18.0
-        } else {
-            8.0
-        };
-        let knockback_y = if attacker.state.has_bonus_knockback {
-            8.432
-        } else {
-            6.432
-        };
-
-        victim
-            .client
-            .set_velocity(DVec3::new(dir.x * knockback_xz, knockback_y, dir.y * knockback_xz));
-
-        attacker.state.has_bonus_knockback = false;
-
-        victim.client.play_sound(
-            Sound::EntityPlayerHurt,
-            SoundCategory::Player,
-            victim.pos.0,
-            1.0,
-            1.0,
-        );
-        victim.client.write_packet(&HurtAnimationS2c {
-            entity_id: VarInt(0),
-            yaw: 0.0,
-        });
-        attacker.client.play_sound(
-            Sound::EntityPlayerHurt,
-            SoundCategory::Player,
-            victim.pos.0,
-            1.0,
-            1.0,
-        );
-        attacker.client.write_packet(&HurtAnimationS2c {
-            entity_id: VarInt(victim.id.get()),
-            yaw: 0.0,
-        });
-
-        victim.boxing_state.hits += 1;
-
-        if victim.boxing_state.hits >= 5 {
-            victim
-                .client
-                .send_chat_message("You have been knocked out!");
-            attacker
-                .client
-                .send_chat_message("You have knocked out your opponent!");
-            end_game.write(EndGameMessage {
-                game_id: victim.gamestate.game_id.unwrap(),
-                loser: victim.gamestate.team,
+            victim.send_chat_message("You have been knocked out!");
+            if let Ok((mut attacker, ..)) = clients.get_mut(hit.attacker) {
+                attacker.send_chat_message("You have knocked out your opponent!");
+            }
+            eliminations.write(EliminationMessage {
+                game_id,
+                player: hit.victim,
             });
         }
     }
 }
 
-fn end_game(
+fn start_round(
     mut clients: Query<&mut BoxingState>,
     games: Query<&Entities>,
-    mut end_game: MessageReader<EndGameMessage>,
+    mut round_start: MessageReader<RoundStartMessage>,
 ) {
-    for message in end_game.read() {
+    for message in round_start.read() {
         let Ok(entities) = games.get(message.game_id) else {
             continue;
         };
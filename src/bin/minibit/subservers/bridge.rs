@@ -1,17 +1,19 @@
 #![allow(clippy::type_complexity)]
 
-use std::marker::PhantomData;
-use std::time::SystemTime;
-
 use crate::ServerConfig;
-use bevy_ecs::query::QueryData;
-use minibit_lib::color::{format, ArmorColors};
+use minibit_lib::color::format;
+use minibit_lib::combat::{
+    CombatPlugin, CombatSet, DamageMessage, KnockbackConfig, KnockbackProfile, OnDeath,
+};
 use minibit_lib::config::WorldValue;
-use minibit_lib::damage::calc_dmg;
-use minibit_lib::damage::calc_dmg_with_weapon;
 use minibit_lib::death::{DeathMessage, DeathPlugin, DeathSet};
+use minibit_lib::duels::game_data::GameDataPlugin;
 use minibit_lib::duels::oob::{OobMode, OobPlugin};
+use minibit_lib::duels::reconnect::Reconnects;
+use minibit_lib::duels::time_limit::TimeLimitOutcome;
 use minibit_lib::duels::*;
+use minibit_lib::duels::kit::{GiveKitMessage, Kit};
+use minibit_lib::duels::rounds::{RoundEndMessage, Rounds};
 use minibit_lib::food::golden_apple::GoldenApplePlugin;
 use minibit_lib::player::*;
 use minibit_lib::projectiles::*;
@@ -21,20 +23,15 @@ use serde::Deserialize;
 use chunkedge::entity::living::Absorption;
 use chunkedge::entity::living::Health;
 use chunkedge::entity::Velocity;
-use chunkedge::entity::{EntityId, EntityStatuses};
 use chunkedge::equipment::EquipmentInventorySync;
-use chunkedge::inventory::HeldItem;
 use chunkedge::math::IVec3;
-use chunkedge::math::Vec3Swizzles;
 use chunkedge::prelude::*;
-use chunkedge::protocol::packets::play::{HurtAnimationS2c, SetExperienceS2c};
-use chunkedge::protocol::sound::SoundCategory;
-use chunkedge::protocol::Sound;
+use chunkedge::protocol::packets::play::SetExperienceS2c;
 use chunkedge::protocol::VarInt;
 use chunkedge::protocol::WritePacket;
 use chunkedge::scoreboard::ObjectiveScores;
 use chunkedge::scoreboard::Objective;
-use chunkedge::item::ItemComponent;
+use std::collections::HashMap;
 
 #[derive(Message)]
 struct ScoreMessage (Entity);
@@ -60,23 +57,50 @@ impl Default for BowStatus {
     }
 }
 
-#[derive(Component, Default)]
+#[derive(Default, Clone, Copy)]
 struct PlayerStatistics {
     kills: u16,
     deaths: u16,
 }
 
+/// Kills and deaths of every player in a game, keyed by UUID so they survive a reconnect
+#[derive(Component, Default)]
+struct GameStatistics(HashMap<u128, PlayerStatistics>);
+
 #[derive(Resource, Deserialize)]
 struct BridgeConfig {
     worlds: Vec<WorldValue>,
     goals: Vec<[i32; 6]>,
     block_restrictions: Vec<[i32; 6]>,
+    kit: Kit,
+    #[serde(default)]
+    knockback: Option<KnockbackConfig>,
+    #[serde(default)]
+    teams: Option<u8>,
+    #[serde(default)]
+    team_size: Option<usize>,
 }
 
 impl DuelsConfig for BridgeConfig {
     fn worlds(&self) -> &Vec<WorldValue> {
         &self.worlds
     }
+
+    fn kit(&self) -> Option<&Kit> {
+        Some(&self.kit)
+    }
+
+    fn knockback(&self) -> Option<&KnockbackConfig> {
+        self.knockback.as_ref()
+    }
+
+    fn teams(&self) -> Option<u8> {
+        self.teams
+    }
+
+    fn team_size(&self) -> Option<usize> {
+        self.team_size
+    }
 }
 
 pub fn main(config: ServerConfig) {
@@ -84,9 +108,14 @@ pub fn main(config: ServerConfig) {
         .add_plugins(DuelsPlugin::<BridgeConfig> {
             path: config.path,
             network_config: config.network,
+            database_url: config.database_url,
+            minigame: "bridge",
             default_gamemode: GameMode::Survival,
             copy_map: true,
-            phantom: PhantomData,
+            rounds_to_win: 5,
+            time_limit_secs: 900,
+            time_limit_outcome: TimeLimitOutcome::Draw,
+            ..Default::default()
         })
         .add_plugins(DefaultPlugins)
         .add_plugins((
@@ -95,9 +124,12 @@ pub fn main(config: ServerConfig) {
                 text: Vec::new(),
                 mode: ScoreboardMode::PerPlayer,
             },
+            GameDataPlugin::<GameStatistics>::default(),
             InteractionBroadcastPlugin,
             DisableDropPlugin,
-            ProjectilePlugin,
+            ProjectilePlugin {
+                arrow_pickup: false,
+            },
             DeathPlugin,
             GoldenApplePlugin,
             DiggingPlugin {
@@ -120,21 +152,32 @@ pub fn main(config: ServerConfig) {
                 bounds_y: 75.0..,
             },
         ))
+        .add_plugins(CombatPlugin {
+            on_death: OnDeath::DeathEvent,
+            // Bridge always carried the victim's last movement over into the knockback
+            knockback: KnockbackProfile {
+                friction: Some(20.0),
+                ..Default::default()
+            },
+            critical_hits: true,
+            ..Default::default()
+        })
         .add_message::<ScoreMessage>()
         .add_message::<MessageMessage>()
         .add_systems(Startup, setup)
-        .add_systems(EventLoopUpdate, handle_combat_events)
         .add_systems(
             Update,
             (
                 init_clients,
-                start_game,
-                gamestage_change,
+                gamestage_change.after(copied_map::place_players::<BridgeConfig>),
                 end_game,
                 check_goals,
                 update_bow_cooldown,
                 handle_collision_events,
-                handle_death.after(DeathSet),
+                handle_death
+                    .after(DeathSet)
+                    .after(CombatSet)
+                    .before(kit::give_kits::<BridgeConfig>),
                 handle_score.after(check_goals).before(handle_death),
                 update_scoreboard.after(handle_score),
                 game_broadcast,
@@ -153,124 +196,61 @@ fn setup(mut commands: Commands, server_config: Res<BridgeConfig>) {
 
 fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
     for entity in clients.iter() {
-        commands.entity(entity).insert((EquipmentInventorySync, BowStatus::default(), PlayerStatistics::default()));
-    }
-}
-
-fn start_game(
-    mut clients: Query<(&mut Inventory, &PlayerGameState, &mut PlayerStatistics), With<Client>>,
-    mut games: Query<(&Entities, &mut GameData)>,
-    mut start_game: MessageReader<StartGameMessage>,
-) {
-    for message in start_game.read() {
-        if let Ok((entities, mut data)) = games.get_mut(message.0) {
-            data.0.insert(0, DataValue::Int(0));
-            data.0.insert(1, DataValue::Int(0));
-
-            for entity in entities.0.iter() {
-                if let Ok((mut inventory, gamestate, mut stats)) = clients.get_mut(*entity) {
-                    fill_inventory(&mut inventory, gamestate.team);
-                    stats.kills = 0;
-                    stats.deaths = 0;
-                }
-            }
-        }
+        commands.entity(entity).insert((EquipmentInventorySync, BowStatus::default()));
     }
 }
 
 fn gamestage_change(
-    mut layers: Query<&mut ChunkLayer>,
-    games: Query<(&MapIndex, &EntityLayerId)>,
+    mut layers: Query<(&mut ChunkLayer, &mut ChangedBlocks)>,
+    games: Query<(&MapIndex, &MapLayer)>,
     server_config: Res<BridgeConfig>,
-    mut gamestage: MessageReader<GameStageMessage>,
+    settings: Res<GameSettings>,
+    mut stage_enter: MessageReader<StageEnterMessage>,
+    mut countdown: MessageReader<CountdownMessage>,
 ) {
-    for message in gamestage.read() {
-        let Ok((map_idx, layer_id)) = games.get(message.game_id) else {
+    // For some reason the blocks are overwritten at the start of the game, so we reapply them every second of the countdown
+    let cage_updates = stage_enter
+        .read()
+        .filter_map(|message| match message.stage {
+            GameStage::Countdown => Some((message.game_id, true)),
+            GameStage::Playing => Some((message.game_id, false)),
+            _ => None,
+        })
+        .chain(countdown.read().map(|message| (message.game_id, true)));
+
+    for (game_id, build) in cage_updates {
+        let Ok((map_idx, map_layer)) = games.get(game_id) else {
             continue;
         };
-        let Ok(mut layer) = layers.get_mut(layer_id.0) else {
+        let Ok((mut layer, mut changes)) = layers.get_mut(map_layer.0) else {
             continue;
         };
 
-        match message.stage {
-            0 | 2 => { // For some reason the blocks are overwritten at the start of the game, so we need to reapply them
-                for i in 0..2 {
-                    let spawn_pos =
-                        DVec3::from_array(server_config.worlds()[map_idx.0].spawns[i].pos);
-                    // Create the cage
-                    for x in -2..=2 {
-                        for y in -1..=3 {
-                            for z in -2..=2 {
-                                if !(-1..=1).contains(&x) || !(0..=2).contains(&y) || !(-1..=1).contains(&z) {
-                                    layer.set_block(
-                                        spawn_pos + DVec3::new(x as f64, y as f64, z as f64),
-                                        BlockState::GLASS,
-                                    );
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            4 => {
-                // Clear the cages
-                for i in 0..2 {
-                    let spawn_pos =
-                        DVec3::from_array(server_config.worlds()[map_idx.0].spawns[i].pos);
-                    for x in -2..=2 {
-                        for y in -1..=3 {
-                            for z in -2..=2 {
-                                layer.set_block(
-                                    spawn_pos + DVec3::new(x as f64, y as f64, z as f64),
-                                    BlockState::AIR,
-                                );
-                            }
+        let world = &server_config.worlds()[map_idx.0];
+        let spawns = (0..settings.teams).flat_map(|team| {
+            (0..settings.team_size as u8).map(move |slot| world.player_spawn(team, slot))
+        });
+        for spawn in spawns {
+            let spawn_pos = DVec3::from_array(spawn.pos);
+            for x in -2..=2 {
+                for y in -1..=3 {
+                    for z in -2..=2 {
+                        let is_wall = !(-1..=1).contains(&x) || !(0..=2).contains(&y) || !(-1..=1).contains(&z);
+                        if build && !is_wall {
+                            continue;
                         }
+                        changes.set_block(
+                            &mut layer,
+                            spawn_pos + DVec3::new(x as f64, y as f64, z as f64),
+                            if build { BlockState::GLASS } else { BlockState::AIR },
+                        );
                     }
                 }
             }
-            _ => {}
         }
     }
 }
 
-fn fill_inventory(inv: &mut Inventory, team: u8) {
-    let armor_components = vec![
-        ItemComponent::DyedColor {
-            color: match team {
-                0 => ArmorColors::Blue as i32,
-                1 => ArmorColors::Red as i32,
-                _ => 0,
-            },
-        }
-    ];
-    let block_type = match team {
-        0 => ItemKind::BlueTerracotta,
-        1 => ItemKind::RedTerracotta,
-        _ => ItemKind::WhiteTerracotta,
-    };
-    inv.set_slot(
-        6,
-        ItemStack::new(ItemKind::LeatherChestplate, 1).with_components(armor_components.clone()),
-    );
-    inv.set_slot(
-        7,
-        ItemStack::new(ItemKind::LeatherLeggings, 1).with_components(armor_components.clone()),
-    );
-    inv.set_slot(8, ItemStack::new(ItemKind::LeatherBoots, 1).with_components(armor_components.clone()));
-    inv.set_slot(36, ItemStack::new(ItemKind::IronSword, 1));
-    inv.set_slot(37, ItemStack::new(ItemKind::Bow, 1));
-    inv.set_slot(38, ItemStack::new(ItemKind::DiamondPickaxe, 1).with_components(vec![
-        ItemComponent::Enchantments(vec![
-            (RegistryId::new(8), VarInt(2)), // Efficiency 2 - trust me
-        ]),
-    ]));
-    inv.set_slot(39, ItemStack::new(block_type, 64));
-    inv.set_slot(40, ItemStack::new(block_type, 64));
-    inv.set_slot(41, ItemStack::new(ItemKind::GoldenApple, 8));
-    inv.set_slot(44, ItemStack::new(ItemKind::Arrow, 1));
-}
-
 fn end_game(
     mut clients: Query<&mut Inventory, With<Client>>,
     games: Query<&Entities>,
@@ -294,9 +274,13 @@ fn check_goals(
     config: Res<BridgeConfig>,
     mut scores: MessageWriter<ScoreMessage>,
     mut deaths: MessageWriter<DeathMessage>,
+    reconnects: Res<Reconnects>,
 ) {
     for (entity, pos, gamestate) in clients.iter() {
-        if gamestate.game_id.is_some() {
+        // Goals don't count while the game waits for a player to reconnect
+        if let Some(game_id) = gamestate.game_id
+            && !reconnects.is_paused(game_id)
+        {
             for (i, goal) in config.goals.iter().enumerate() {
                 if (goal[0]..=goal[1]).contains(&(pos.0.x as i32))
                     && (goal[2]..=goal[3]).contains(&(pos.0.y as i32))
@@ -352,131 +336,23 @@ fn update_bow_cooldown(
     }
 }
 
-#[derive(QueryData)]
-#[query_data(mutable)]
-struct CombatQuery {
-    entity: Entity,
-    client: &'static mut Client,
-    id: &'static EntityId,
-    pos: &'static Position,
-    old_pos: &'static OldPosition,
-    state: &'static mut CombatState,
-    statuses: &'static mut EntityStatuses,
-    gamestate: &'static PlayerGameState,
-    health: &'static mut Health,
-    absorption: &'static mut Absorption,
-    held_item: &'static HeldItem,
-    inv: &'static Inventory,
-}
-
-fn handle_combat_events(
-    server: Res<Server>,
-    mut clients: Query<CombatQuery>,
-    mut sprinting: MessageReader<SprintMessage>,
-    mut interact_entity: MessageReader<InteractEntityMessage>,
-    mut deaths: MessageWriter<DeathMessage>,
-) {
-    for &SprintMessage { client, state } in sprinting.read() {
-        if let Ok(mut client) = clients.get_mut(client) {
-            client.state.has_bonus_knockback = state == SprintState::Start;
-        }
-    }
-
-    for &InteractEntityMessage {
-        client: attacker_client,
-        entity: victim_client,
-        interact: interaction,
-        ..
-    } in interact_entity.read()
-    {
-        let Ok([mut attacker, mut victim]) = clients.get_many_mut([attacker_client, victim_client])
-        else {
-            continue;
-        };
-
-        if interaction != EntityInteraction::Attack
-            || server.current_tick() - victim.state.last_attacked_tick < 10
-            || attacker.gamestate.team == victim.gamestate.team
-            || attacker.gamestate.game_id != victim.gamestate.game_id
-        {
-            continue;
-        }
-
-        victim.state.last_attacked_tick = server.current_tick();
-
-        let victim_pos = victim.pos.0.xz();
-        let attacker_pos = attacker.pos.0.xz();
-
-        let dir = (victim_pos - attacker_pos).normalize().as_vec2();
-
-        let knockback_xz = if attacker.state.has_bonus_knockback {
-            18.0
-        } else {
-            8.0
-        };
-        let knockback_y = if attacker.state.has_bonus_knockback {
-            8.432
-        } else {
-            6.432
-        };
-
-        let dmg = calc_dmg_with_weapon(
-            attacker.inv.slot(attacker.held_item.slot()).item,
-            victim.inv.slot(5).item,
-            victim.inv.slot(6).item,
-            victim.inv.slot(7).item,
-            victim.inv.slot(8).item
-        );
-
-        damage_player(
-            &mut attacker,
-            &mut victim,
-            dmg,
-            Vec3::new(dir.x * knockback_xz, knockback_y, dir.y * knockback_xz),
-            &mut deaths,
-        );
-
-        attacker.state.has_bonus_knockback = false;
-    }
-}
-
 fn handle_collision_events(
-    mut clients: Query<CombatQuery>,
     arrows: Query<(&Velocity, &ProjectileOwner)>,
     mut collisions: MessageReader<ProjectileCollisionMessage>,
-    mut deaths: MessageWriter<DeathMessage>,
+    mut damage: MessageWriter<DamageMessage>,
 ) {
     for message in collisions.read() {
-        if let Ok((vel, owner)) = arrows.get(message.arrow)
-            && let Ok([mut attacker, mut victim]) = clients.get_many_mut([owner.0, message.player])
-        {
-            if attacker.gamestate.team == victim.gamestate.team {
-                continue;
-            }
-
-            // TODO: Make the damage accurate
-            let dmg = calc_dmg(
-                (0.13 * vel.0.length()) as f32,
-                victim.inv.slot(5).item,
-                victim.inv.slot(6).item,
-                victim.inv.slot(7).item,
-                victim.inv.slot(8).item
-            );
-
-            damage_player(
-                &mut attacker,
-                &mut victim,
-                dmg,
-                vel.0.normalize().with_y(0.0).as_vec3() * 0.6 * 20.0, // TODO: Make the knockback accurate
-                &mut deaths,
-            );
-            attacker.client.play_sound(
-                Sound::EntityArrowHitPlayer,
-                SoundCategory::Player,
-                attacker.pos.0,
-                1.0,
-                1.0,
-            );
+        if let Ok((vel, owner)) = arrows.get(message.arrow) {
+            damage.write(DamageMessage {
+                attacker: owner.0,
+                victim: message.player,
+                // TODO: Make the damage accurate
+                damage: (0.13 * vel.0.length()) as f32,
+                // TODO: Make the knockback accurate
+                knockback: vel.0.normalize().with_y(0.0).as_vec3() * 0.6 * 20.0,
+                projectile: true,
+                critical: false,
+            });
         }
     }
 }
@@ -493,14 +369,15 @@ fn handle_death(
             &Username,
             &PlayerGameState,
             &mut CombatState,
-            &mut PlayerStatistics,
         ),
         With<Client>,
     >,
     usernames: Query<&Username, With<Client>>,
-    games: Query<&MapIndex>,
+    unique_ids: Query<&UniqueId, With<Client>>,
+    mut games: Query<(&MapIndex, &mut GameStatistics)>,
     mut deaths: MessageReader<DeathMessage>,
     mut broadcasts: MessageWriter<MessageMessage>,
+    mut give_kit: MessageWriter<GiveKitMessage>,
     config: Res<BridgeConfig>,
 ) {
     let mut killers = Vec::new();
@@ -515,18 +392,18 @@ fn handle_death(
             username,
             gamestate,
             mut combatstate,
-            mut stats,
         )) = clients.get_mut(*entity)
             && let Some(game_id) = gamestate.game_id
-            && let Ok(map_index) = games.get(game_id)
+            && let Ok((map_index, mut stats)) = games.get_mut(game_id)
         {
-            if *show {
-                stats.deaths += 1;
-                if let Some(last_attacker) = combatstate.last_attacker {
-                    killers.push(last_attacker);
-                }
+            if *show && let Ok(unique_id) = unique_ids.get(*entity) {
+                stats.0.entry(unique_id.0.as_u128()).or_default().deaths += 1;
+            }
+            if *show && let Some(last_attacker) = combatstate.last_attacker {
+                killers.push((game_id, last_attacker));
             }
-            let spawn = &config.worlds[map_index.0].spawns[gamestate.team as usize];
+            let spawn =
+                config.worlds[map_index.0].player_spawn(gamestate.team, gamestate.team_slot);
             pos.0 = spawn.pos.into();
             look.yaw = spawn.rot[0];
             look.pitch = spawn.rot[1];
@@ -536,7 +413,7 @@ fn handle_death(
             for slot in 0..inventory.slot_count() {
                 inventory.set_slot(slot, ItemStack::EMPTY);
             }
-            fill_inventory(&mut inventory, gamestate.team);
+            give_kit.write(GiveKitMessage(*entity));
             if *show {
                 broadcasts.write(MessageMessage {
                     game: game_id,
@@ -563,21 +440,22 @@ fn handle_death(
             combatstate.last_attacker = None;
         }
     }
-    for killer in killers {
-        if let Ok(mut killer) = clients.get_mut(killer) {
-            killer.9.kills += 1;
+    for (game_id, killer) in killers {
+        if let Ok((_, mut stats)) = games.get_mut(game_id)
+            && let Ok(unique_id) = unique_ids.get(killer)
+        {
+            stats.0.entry(unique_id.0.as_u128()).or_default().kills += 1;
         }
     }
 }
 
 fn handle_score(
     clients: Query<(&Username, &PlayerGameState), With<Client>>,
-    mut games: Query<(&Entities, &mut GameStage, &mut GameTime, &mut GameData)>,
+    games: Query<(&GameStage, &Rounds)>,
     mut scores: MessageReader<ScoreMessage>,
-    mut deaths: MessageWriter<DeathMessage>,
     mut broadcasts: MessageWriter<MessageMessage>,
-    mut gamestage: MessageWriter<GameStageMessage>,
-    mut end_game: MessageWriter<EndGameMessage>,
+    mut round_end: MessageWriter<RoundEndMessage>,
+    settings: Res<GameSettings>,
 ) {
     for ScoreMessage(player) in scores.read() {
         let Ok((username, gamestate)) = clients.get(*player) else {
@@ -586,18 +464,14 @@ fn handle_score(
         let Some(game) = gamestate.game_id else {
             continue;
         };
-        let Ok((entities, mut stage, mut time, mut data)) = games.get_mut(game) else {
+        let Ok((stage, rounds)) = games.get(game) else {
             continue;
         };
-        let team = gamestate.team as usize;
-        let mut score = 0;
-        if let Some(DataValue::Int(old_score)) = data.0.get(&(team)) {
-            score = *old_score + 1;
-        }
-        data.0.insert(team, DataValue::Int(score));
-        for entity in entities.0.iter() {
-            deaths.write(DeathMessage(*entity, false));
+        if *stage != GameStage::Playing {
+            continue;
         }
+        let team = gamestate.team as usize;
+        let score = rounds.wins[team] + 1;
         broadcasts.write(MessageMessage {
             game,
             msg: Text::from(username.0.clone()).color(
@@ -608,38 +482,32 @@ fn handle_score(
                 },
             ) + Text::from(" scored! (").color(Color::GRAY)
                 + Text::from(score.to_string()).color(Color::GOLD)
-                + Text::from("/5)").color(Color::GRAY),
+                + Text::from(format!("/{})", settings.rounds_to_win)).color(Color::GRAY),
+        });
+        round_end.write(RoundEndMessage {
+            game_id: game,
+            result: GameResult::Winner(gamestate.team),
         });
-        if score >= 5 {
-            end_game.write(EndGameMessage {
-                game_id: game,
-                loser: if team == 0 { 1 } else { 0 },
-            });
-        } else {
-            time.0 = SystemTime::now(); // TODO: Replace with a better solution because the game time is not supposed to keep track of the entire game duration
-            stage.0 = 0;
-            gamestage.write(GameStageMessage {
-                game_id: game,
-                stage: 0,
-            });
-        }
     }
 }
 
 // TODO: Optimize
 fn update_scoreboard(
-    games: Query<(Ref<GameData>, &Entities)>,
-    clients: Query<(&ScoreboardId, Ref<PlayerStatistics>)>,
+    games: Query<(Ref<Rounds>, Ref<GameStatistics>, &Entities)>,
+    clients: Query<(Ref<ScoreboardId>, &UniqueId)>,
     mut objectives: Query<&mut ObjectiveScores, With<Objective>>,
+    settings: Res<GameSettings>,
 ) {
-    for (data, entities) in games.iter() {
-        if let Some(DataValue::Int(blue)) = data.0.get(&0) && let Some(DataValue::Int(red)) = data.0.get(&1) {
+    for (rounds, game_stats, entities) in games.iter() {
+        if let [blue, red] = rounds.wins.as_slice() {
             for entity in entities.0.iter() {
-                if let Ok((scoreboard_id, stats)) = clients.get(*entity) && let Ok(mut scores) = objectives.get_mut(scoreboard_id.0) {
-                    if !data.is_changed() && !stats.is_changed() {
+                if let Ok((scoreboard_id, unique_id)) = clients.get(*entity) && let Ok(mut scores) = objectives.get_mut(scoreboard_id.0) {
+                    // Players that just (re)joined get a new scoreboard
+                    if !rounds.is_changed() && !game_stats.is_changed() && !scoreboard_id.is_changed() {
                         continue;
                     }
-                    *scores = gen_scores(&gen_text(*red, *blue, stats.kills, stats.deaths));
+                    let stats = game_stats.0.get(&unique_id.0.as_u128()).copied().unwrap_or_default();
+                    *scores = gen_scores(&gen_text(*red, *blue, settings.rounds_to_win, stats.kills, stats.deaths));
                 }
             }
         }
@@ -664,66 +532,12 @@ fn game_broadcast(
 
 // Helper functions below
 
-fn gen_text(red: i32, blue: i32, kills: u16, deaths: u16) -> Vec<String> {
+fn gen_text(red: u32, blue: u32, goals: u32, kills: u16, deaths: u16) -> Vec<String> {
     vec![
-        format::RED.to_string()+"[R] "+"\u{2B24}".repeat(red as usize).as_str()+format::GRAY+"\u{2B24}".repeat(5 - red as usize).as_str(),
-        format::BLUE.to_string()+"[B] "+"\u{2B24}".repeat(blue as usize).as_str()+format::GRAY+"\u{2B24}".repeat(5 - blue as usize).as_str(),
+        format::RED.to_string()+"[R] "+"\u{2B24}".repeat(red as usize).as_str()+format::GRAY+"\u{2B24}".repeat(goals.saturating_sub(red) as usize).as_str(),
+        format::BLUE.to_string()+"[B] "+"\u{2B24}".repeat(blue as usize).as_str()+format::GRAY+"\u{2B24}".repeat(goals.saturating_sub(blue) as usize).as_str(),
         "".to_string(),
         format::WHITE.to_string()+"Kills: "+itoa::Buffer::new().format(kills),
         format::WHITE.to_string()+"Deaths: "+itoa::Buffer::new().format(deaths),
     ]
 }
-
-fn damage_player(
-    attacker: &mut CombatQueryItem,
-    victim: &mut CombatQueryItem,
-    damage: f32,
-    velocity: Vec3,
-    deaths: &mut MessageWriter<DeathMessage>,
-) {
-    let old_vel = Vec3::new(
-        (victim.pos.0.x - victim.old_pos.get().x) as f32,
-        (victim.pos.0.y - victim.old_pos.get().y) as f32,
-        (victim.pos.0.z - victim.old_pos.get().z) as f32,
-    );
-
-    victim.client.set_velocity(old_vel + velocity);
-
-    attacker.state.has_bonus_knockback = false;
-
-    victim.client.play_sound(
-        Sound::EntityPlayerHurt,
-        SoundCategory::Player,
-        victim.pos.0,
-        1.0,
-        1.0,
-    );
-    victim.client.write_packet(&HurtAnimationS2c {
-        entity_id: VarInt(0),
-        yaw: 0.0,
-    });
-    attacker.client.play_sound(
-        Sound::EntityPlayerHurt,
-        SoundCategory::Player,
-        victim.pos.0,
-        1.0,
-        1.0,
-    );
-    attacker.client.write_packet(&HurtAnimationS2c {
-        entity_id: VarInt(victim.id.get()),
-        yaw: 0.0,
-    });
-
-    victim.state.last_attacker = Some(attacker.entity);
-
-    let mut new_damage = damage;
-    if victim.absorption.0 > 0.0 {
-        new_damage -= damage.min(victim.absorption.0);
-        victim.absorption.0 -= damage.min(victim.absorption.0);
-    }
-    if victim.health.0 <= new_damage {
-        deaths.write(DeathMessage(victim.entity, true));
-    } else {
-        victim.health.0 -= new_damage;
-    }
-}
\ No newline at end of file
@@ -1,18 +1,10 @@
 #![allow(clippy::type_complexity)]
 
-use std::marker::PhantomData;
-use bevy_ecs::query::QueryData;
-use minibit_lib::duels::{CombatState, DefaultDuelsConfig, DuelsPlugin, EndGameMessage, Entities, PlayerGameState, StartGameMessage};
-use chunkedge::entity::living::Health;
-use chunkedge::entity::Velocity;
-use chunkedge::entity::{EntityId, EntityStatuses};
-use chunkedge::math::Vec3Swizzles;
+use minibit_lib::combat::{CombatPlugin, MeleeDamage};
+use minibit_lib::duels::{DefaultDuelsConfig, DuelsPlugin, EndGameMessage, Entities};
+use minibit_lib::duels::time_limit::TimeLimitOutcome;
+use minibit_lib::food::consumables::{ConsumablesPlugin, SOUP};
 use chunkedge::prelude::*;
-use chunkedge::protocol::packets::play::HurtAnimationS2c;
-use chunkedge::protocol::sound::SoundCategory;
-use chunkedge::protocol::Sound;
-use chunkedge::protocol::VarInt;
-use chunkedge::protocol::WritePacket;
 use minibit_lib::duels::oob::{OobMode, OobPlugin};
 use crate::ServerConfig;
 
@@ -21,43 +13,41 @@ pub fn main(config: ServerConfig) {
         .add_plugins(DuelsPlugin::<DefaultDuelsConfig> {
             path: config.path,
             network_config: config.network,
+            database_url: config.database_url,
+            minigame: "classic",
             default_gamemode: GameMode::Adventure,
             copy_map: false,
-            phantom: PhantomData
+            rounds_to_win: 2,
+            time_limit_secs: 300,
+            time_limit_outcome: TimeLimitOutcome::OneHitKills,
+            ..Default::default()
         })
         .add_plugins(DefaultPlugins)
         .add_plugins(OobPlugin {
-            mode: OobMode::GameEndEvent,
+            mode: OobMode::Eliminate,
             bounds_y: 0.0..,
         })
-        .add_systems(EventLoopUpdate, handle_combat_events)
-        .add_systems(Update, (start_game, end_game))
+        .add_plugins(CombatPlugin {
+            melee_damage: MeleeDamage::Fixed(5.83),
+            critical_hits: true,
+            sword_block: Some(0.5),
+            ..Default::default()
+        })
+        .add_plugins(ConsumablesPlugin {
+            consumables: vec![SOUP],
+        })
+        .add_systems(Update, end_game)
         .run();
 }
 
-fn start_game(
+/// Clears the kits of a finished game's players.
+pub fn end_game(
     mut clients: Query<&mut Inventory>,
     games: Query<&Entities>,
-    mut start_game: MessageReader<StartGameMessage>,
+    mut end_game: MessageReader<EndGameMessage>,
 ) {
-    for message in start_game.read() {
-        if let Ok(entities) = games.get(message.0) {
-            for entity in entities.0.iter() {
-                if let Ok(mut inv) = clients.get_mut(*entity) {
-                    inv.set_slot(36, ItemStack::new(ItemKind::IronSword, 1));
-                }
-            }
-        }
-    }
-}
-
-fn end_game(
-    mut clients: Query<&mut Inventory>,
-    games: Query<&Entities>,
-    mut start_game: MessageReader<StartGameMessage>,
-) {
-    for message in start_game.read() {
-        if let Ok(entities) = games.get(message.0) {
+    for message in end_game.read() {
+        if let Ok(entities) = games.get(message.game_id) {
             for entity in entities.0.iter() {
                 if let Ok(mut inv) = clients.get_mut(*entity) {
                     for slot in 0..inv.slot_count() {
@@ -68,107 +58,3 @@ fn end_game(
         }
     }
 }
-
-#[derive(QueryData)]
-#[query_data(mutable)]
-struct CombatQuery {
-    client: &'static mut Client,
-    id: &'static EntityId,
-    pos: &'static Position,
-    vel: &'static mut Velocity,
-    health: &'static mut Health,
-    state: &'static mut CombatState,
-    statuses: &'static mut EntityStatuses,
-    gamestate: &'static PlayerGameState,
-}
-
-fn handle_combat_events(
-    server: Res<Server>,
-    mut clients: Query<CombatQuery>,
-    mut sprinting: MessageReader<SprintMessage>,
-    mut interact_entity: MessageReader<InteractEntityMessage>,
-    mut end_game: MessageWriter<EndGameMessage>,
-) {
-    for &SprintMessage { client, state } in sprinting.read() {
-        if let Ok(mut client) = clients.get_mut(client) {
-            client.state.has_bonus_knockback = state == SprintState::Start;
-        }
-    }
-
-    for &InteractEntityMessage {
-        client: attacker_client,
-        entity: victim_client,
-        interact: interaction,
-        ..
-    } in interact_entity.read()
-    {
-        let Ok([mut attacker, mut victim]) = clients.get_many_mut([attacker_client, victim_client])
-        else {
-            continue;
-        };
-
-        if interaction != EntityInteraction::Attack
-            || server.current_tick() - victim.state.last_attacked_tick < 10
-            || attacker.gamestate.game_id != victim.gamestate.game_id
-        {
-            continue;
-        }
-
-        victim.state.last_attacked_tick = server.current_tick();
-
-        let victim_pos = victim.pos.0.xz();
-        let attacker_pos = attacker.pos.0.xz();
-
-        let dir = (victim_pos - attacker_pos).normalize();
-
-        let knockback_xz = if attacker.state.has_bonus_knockback {
-            18.0
-        } else {
-            8.0
-        };
-        let knockback_y = if attacker.state.has_bonus_knockback {
-            8.432
-        } else {
-            6.432
-        };
-
-        victim
-            .client
-            .set_velocity(DVec3::new(dir.x * knockback_xz, knockback_y, dir.y * knockback_xz));
-
-        let damage = 5.83;
-        if victim.health.0 > damage {
-            victim.health.0 -= damage;
-        } else {
-            end_game.write(EndGameMessage {
-                game_id: victim.gamestate.game_id.unwrap(),
-                loser: victim.gamestate.team,
-            });
-        }
-
-        attacker.state.has_bonus_knockback = false;
-
-        victim.client.play_sound(
-            Sound::EntityPlayerHurt,
-            SoundCategory::Player,
-            victim.pos.0,
-            1.0,
-            1.0,
-        );
-        victim.client.write_packet(&HurtAnimationS2c {
-            entity_id: VarInt(0),
-            yaw: 0.0,
-        });
-        attacker.client.play_sound(
-            Sound::EntityPlayerHurt,
-            SoundCategory::Player,
-            victim.pos.0,
-            1.0,
-            1.0,
-        );
-        attacker.client.write_packet(&HurtAnimationS2c {
-            entity_id: VarInt(victim.id.get()),
-            yaw: 0.0,
-        });
-    }
-}
@@ -0,0 +1,45 @@
+#![allow(clippy::type_complexity)]
+
+use minibit_lib::combat::CombatPlugin;
+use minibit_lib::duels::{DefaultDuelsConfig, DuelsPlugin};
+use minibit_lib::duels::time_limit::TimeLimitOutcome;
+use minibit_lib::food::consumables::{ConsumablesPlugin, STEAK};
+use minibit_lib::food::hunger::HungerPlugin;
+use chunkedge::prelude::*;
+use minibit_lib::duels::oob::{OobMode, OobPlugin};
+use crate::ServerConfig;
+use crate::subservers::classic;
+
+/// Classic duels played with 1.9+ combat, which the mode's config switches to, and hunger
+pub fn main(config: ServerConfig) {
+    App::new()
+        .add_plugins(DuelsPlugin::<DefaultDuelsConfig> {
+            path: config.path,
+            network_config: config.network,
+            database_url: config.database_url,
+            minigame: "modern",
+            default_gamemode: GameMode::Adventure,
+            copy_map: false,
+            rounds_to_win: 2,
+            time_limit_secs: 300,
+            time_limit_outcome: TimeLimitOutcome::OneHitKills,
+            ..Default::default()
+        })
+        .add_plugins(DefaultPlugins)
+        .add_plugins(OobPlugin {
+            mode: OobMode::Eliminate,
+            bounds_y: 0.0..,
+        })
+        .add_plugins(CombatPlugin {
+            critical_hits: true,
+            ..Default::default()
+        })
+        .add_plugins((
+            HungerPlugin,
+            ConsumablesPlugin {
+                consumables: vec![STEAK],
+            },
+        ))
+        .add_systems(Update, classic::end_game)
+        .run();
+}
@@ -0,0 +1,943 @@
+#![allow(clippy::type_complexity)]
+
+use crate::damage::{attack_speed, calc_dmg, is_axe, is_sword, item_dmg, modern_item_dmg};
+use crate::death::DeathMessage;
+use crate::duels::reconnect::Reconnects;
+use crate::duels::stats::HitMessage;
+use crate::duels::{
+    CombatState, DuelsConfig, EliminationMessage, GameStage, MapIndex, PlayerGameState,
+    StageEnterMessage,
+};
+use crate::effects::{StatusEffect, StatusEffects};
+use crate::food::hunger::Hunger;
+use bevy_ecs::query::QueryData;
+use chunkedge::entity::attributes::{EntityAttribute, EntityAttributes};
+use chunkedge::entity::{EntityId, OnGround};
+use chunkedge::entity::living::{Absorption, Health};
+use chunkedge::event_loop::PacketMessage;
+use chunkedge::interact_item::InteractItemMessage;
+use chunkedge::inventory::player_inventory::PlayerInventory;
+use chunkedge::inventory::{HeldItem, PlayerAction};
+use chunkedge::math::{DVec2, Vec3Swizzles};
+use chunkedge::{Hand, ItemKind};
+use chunkedge::prelude::*;
+use chunkedge::protocol::packets::play::{
+    CooldownUpdateS2c, HurtAnimationS2c, PlayerActionC2s, SwingC2s,
+};
+use chunkedge::protocol::sound::SoundCategory;
+use chunkedge::protocol::{Sound, VarInt, WritePacket};
+use chunkedge::keepalive::Ping;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use std::collections::{HashMap, VecDeque};
+use std::marker::PhantomData;
+
+/// How far back hits can be rewound, which caps the ping that is compensated for
+const HISTORY_TICKS: usize = 20;
+const EYE_HEIGHT: f64 = 1.62;
+const PLAYER_WIDTH: f64 = 0.6;
+const PLAYER_HEIGHT: f64 = 1.8;
+const CRIT_MULTIPLIER: f32 = 1.5;
+/// A combo ends if the victim isn't hit again within this many ticks after being invulnerable
+const COMBO_GAP_TICKS: i64 = 10;
+/// Rejected hits are logged at most this often per player, with how many there were
+const REJECTED_LOG_TICKS: i64 = 100;
+/// Clients repeat the use packet every 4 ticks while right click is held, and only send a release
+/// for items that can be used, which swords can't be in modern versions
+const BLOCK_TIMEOUT_TICKS: i64 = 5;
+/// Cooldown progress above which a 1.9+ attack counts as charged, which crits, sprint knockback
+/// and sweeps need
+const CHARGED_COOLDOWN: f32 = 0.9;
+const SWEEP_DAMAGE: f32 = 1.0;
+/// Share of the knockback profile that sweep hits push with, since vanilla's sweeps only have a
+/// strength of 0.4
+const SWEEP_KNOCKBACK: f32 = 0.4;
+/// Shields only block once they have been raised for this long
+const SHIELD_DELAY_TICKS: i64 = 5;
+/// How long an axe hit disables a shield for
+const SHIELD_DISABLE_TICKS: i64 = 100;
+/// Vanilla's base attack speed, with which items bring their own cooldown
+const MODERN_ATTACK_SPEED: f64 = 4.0;
+/// High enough that no item shows a cooldown, since legacy rules don't have one
+const LEGACY_ATTACK_SPEED: f64 = 1024.0;
+
+/// Applies every `DamageMessage`. Modes that react to hits, e.g. by reading `HitMessage`, should
+/// run after this set.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CombatSet;
+
+/// Damage dealt by one player to another, before armor. Melee hits are sent by the plugin itself,
+/// modes send these for anything else that hurts, like arrows.
+#[derive(Message)]
+pub struct DamageMessage {
+    pub attacker: Entity,
+    pub victim: Entity,
+    pub damage: f32,
+    pub knockback: Vec3,
+    /// Whether the damage was from a projectile instead of melee
+    pub projectile: bool,
+    /// Whether the damage was a critical hit, which only adds particles and a sound here
+    pub critical: bool,
+}
+
+/// How players are knocked back by hits. Velocities are in blocks per second.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct KnockbackProfile {
+    pub horizontal: f32,
+    pub vertical: f32,
+    /// Added to the knockback of hits from a sprinting attacker
+    pub sprint_horizontal: f32,
+    pub sprint_vertical: f32,
+    /// Opts into carrying the victim's momentum over, divided by this, into the knockback.
+    /// Vanilla uses 2. Without it the victim is only left with the knockback.
+    pub friction: Option<f32>,
+    /// Multiplies the knockback of victims that are in the air
+    pub air_multiplier: f32,
+    /// Highest upwards velocity a hit can leave the victim with
+    pub vertical_limit: Option<f32>,
+    /// Share of the knockback that is taken off for every hit a victim took in a row from the same
+    /// attacker
+    pub combo_reduction: f32,
+}
+
+impl KnockbackProfile {
+    /// Describes the first field that can't be used, if any.
+    pub fn validate(&self) -> Result<(), String> {
+        // The victim's momentum is divided by it
+        if let Some(friction) = self.friction
+            && (friction <= 0.0 || friction.is_nan())
+        {
+            return Err(format!("friction has to be above 0, but is {}", friction));
+        }
+        Ok(())
+    }
+}
+
+impl Default for KnockbackProfile {
+    fn default() -> Self {
+        Self {
+            horizontal: 8.0,
+            vertical: 6.432,
+            sprint_horizontal: 10.0,
+            sprint_vertical: 2.0,
+            friction: None,
+            air_multiplier: 1.0,
+            vertical_limit: None,
+            combo_reduction: 0.0,
+        }
+    }
+}
+
+/// The knockback profiles of a mode's `config.json`
+#[derive(Deserialize, Default)]
+pub struct KnockbackConfig {
+    #[serde(deserialize_with = "deserialize_profiles")]
+    pub profiles: HashMap<String, KnockbackProfile>,
+    /// Profile used on maps that don't pick one, or the built in one if unset
+    pub default: Option<String>,
+}
+
+/// Fails loading the config if one of the profiles can't be used.
+fn deserialize_profiles<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, KnockbackProfile>, D::Error> {
+    let profiles = HashMap::<String, KnockbackProfile>::deserialize(deserializer)?;
+    for (name, profile) in profiles.iter() {
+        profile
+            .validate()
+            .map_err(|err| D::Error::custom(format!("knockback profile `{}`: {}", name, err)))?;
+    }
+    Ok(profiles)
+}
+
+impl KnockbackConfig {
+    fn profile(&self, name: Option<&String>) -> Option<&KnockbackProfile> {
+        name.or(self.default.as_ref()).map(|name| &self.profiles[name])
+    }
+}
+
+/// The knockback profile of a game, picked for its map. Games without one use the profile of
+/// `CombatSettings`.
+#[derive(Component)]
+pub struct Knockback(pub KnockbackProfile);
+
+/// Which version's combat mechanics a mode uses
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CombatRules {
+    /// 1.8 combat, where every click deals full damage
+    Legacy,
+    /// 1.9+ combat, with the attack cooldown, sweep attacks and shields that axes disable
+    Modern,
+}
+
+/// How much a melee hit deals before armor
+#[derive(Clone, Copy)]
+pub enum MeleeDamage {
+    /// Hits only knock players back
+    None,
+    Fixed(f32),
+    /// The held item's damage
+    Weapon,
+}
+
+/// What happens to a player that takes lethal damage
+#[derive(Clone, Copy)]
+pub enum OnDeath {
+    /// A `DeathMessage` is sent so that the mode can respawn the player
+    DeathEvent,
+    Eliminate,
+}
+
+#[derive(Resource)]
+pub struct CombatSettings {
+    pub rules: CombatRules,
+    pub melee_damage: MeleeDamage,
+    pub on_death: OnDeath,
+    /// Ticks after a melee hit during which the victim can't be hit again
+    pub invulnerable_ticks: i64,
+    pub knockback: KnockbackProfile,
+    /// Farthest distance from the attacker's eyes to the victim's hitbox that a hit is accepted
+    /// at, after rewinding the victim by the attacker's ping
+    pub reach: f64,
+    /// Whether melee hits of falling attackers deal 50% more damage
+    pub critical_hits: bool,
+    /// Multiplies the damage taken by players blocking with a sword, like in 1.8. Swords can't
+    /// block if unset.
+    pub sword_block: Option<f32>,
+}
+
+/// Where a player was during the last ticks, newest first
+#[derive(Component, Default)]
+pub struct PositionHistory(pub VecDeque<DVec3>);
+
+impl PositionHistory {
+    /// The position of the player `ticks` ago, or the oldest one that is known.
+    pub fn rewind(&self, ticks: usize) -> Option<DVec3> {
+        self.0.get(ticks).or(self.0.back()).copied()
+    }
+}
+
+/// Melee hits between players of different teams in the same game, along with knockback, damage,
+/// absorption and deaths for every kind of damage.
+pub struct CombatPlugin {
+    /// Used unless the mode's config picks its own rules
+    pub rules: CombatRules,
+    pub melee_damage: MeleeDamage,
+    pub on_death: OnDeath,
+    pub invulnerable_ticks: i64,
+    pub knockback: KnockbackProfile,
+    pub reach: f64,
+    pub critical_hits: bool,
+    pub sword_block: Option<f32>,
+}
+
+impl Default for CombatPlugin {
+    fn default() -> Self {
+        Self {
+            rules: CombatRules::Legacy,
+            melee_damage: MeleeDamage::Weapon,
+            on_death: OnDeath::Eliminate,
+            invulnerable_ticks: 10,
+            knockback: KnockbackProfile::default(),
+            // Vanilla's reach, with some room for movement between packets
+            reach: 3.3,
+            critical_hits: false,
+            sword_block: None,
+        }
+    }
+}
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        if let Err(err) = self.knockback.validate() {
+            panic!("The built in knockback profile is invalid: {}", err);
+        }
+        app.insert_resource(CombatSettings {
+            rules: self.rules,
+            melee_damage: self.melee_damage,
+            on_death: self.on_death,
+            invulnerable_ticks: self.invulnerable_ticks,
+            knockback: self.knockback.clone(),
+            reach: self.reach,
+            critical_hits: self.critical_hits,
+            sword_block: self.sword_block,
+        })
+        .add_message::<DamageMessage>()
+        .add_message::<SweepMessage>()
+        // Sent even by modes without the death plugin, as long as they don't use it
+        .add_message::<DeathMessage>()
+        .add_systems(EventLoopUpdate, handle_melee)
+        .add_systems(
+            Update,
+            (
+                init_clients,
+                set_attack_speed,
+                record_positions,
+                reset_cooldown,
+                track_blocking.before(CombatSet),
+                apply_damage.in_set(CombatSet),
+                play_sweep_effects,
+            ),
+        );
+    }
+}
+
+/// Picks the combat rules of the mode and the knockback profile of every game from the mode's
+/// config, the latter once the game's map is known.
+pub struct KnockbackPlugin<T: Resource + DuelsConfig> {
+    pub phantom: PhantomData<T>,
+}
+
+impl<T: Resource + DuelsConfig> Plugin for KnockbackPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, (check_knockback::<T>, select_rules::<T>))
+            .add_systems(Update, select_knockback::<T>);
+    }
+}
+
+fn select_rules<T: Resource + DuelsConfig>(
+    settings: Option<ResMut<CombatSettings>>,
+    config: Res<T>,
+) {
+    if let Some(mut settings) = settings
+        && let Some(rules) = config.combat_rules()
+    {
+        settings.rules = rules;
+    }
+}
+
+/// Fails on startup instead of mid-game if a profile name doesn't exist. The profiles themselves
+/// are checked when the config is loaded.
+fn check_knockback<T: Resource + DuelsConfig>(config: Res<T>) {
+    let Some(knockback) = config.knockback() else {
+        return;
+    };
+    let names = config
+        .worlds()
+        .iter()
+        .filter_map(|world| world.knockback.as_ref())
+        .chain(knockback.default.as_ref());
+    for name in names {
+        if !knockback.profiles.contains_key(name) {
+            panic!("Unknown knockback profile: {}", name);
+        }
+    }
+}
+
+fn select_knockback<T: Resource + DuelsConfig>(
+    games: Query<&MapIndex>,
+    mut stage_enter: MessageReader<StageEnterMessage>,
+    mut commands: Commands,
+    config: Res<T>,
+) {
+    let Some(knockback) = config.knockback() else {
+        return;
+    };
+    for event in stage_enter.read() {
+        if event.stage != GameStage::Countdown {
+            continue;
+        }
+        let Ok(map) = games.get(event.game_id) else {
+            continue;
+        };
+        if let Some(profile) = knockback.profile(config.worlds()[map.0].knockback.as_ref()) {
+            commands
+                .entity(event.game_id)
+                .try_insert(Knockback(profile.clone()));
+        }
+    }
+}
+
+fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
+    for entity in clients.iter() {
+        commands.entity(entity).insert(PositionHistory::default());
+    }
+}
+
+/// Legacy rules hide the client's cooldown indicator, while modern ones use vanilla's base speed.
+fn set_attack_speed(
+    mut clients: Query<&mut EntityAttributes, Added<Client>>,
+    settings: Res<CombatSettings>,
+) {
+    let speed = match settings.rules {
+        CombatRules::Legacy => LEGACY_ATTACK_SPEED,
+        CombatRules::Modern => MODERN_ATTACK_SPEED,
+    };
+    for mut attributes in clients.iter_mut() {
+        attributes.set_base_value(EntityAttribute::GenericAttackSpeed, speed);
+    }
+}
+
+/// Switching items restarts the attack cooldown, like in vanilla.
+fn reset_cooldown(mut players: Query<&mut CombatState, Changed<HeldItem>>, server: Res<Server>) {
+    for mut state in players.iter_mut() {
+        state.last_swing_tick = server.current_tick();
+    }
+}
+
+fn record_positions(mut players: Query<(&Position, &mut PositionHistory)>) {
+    for (pos, mut history) in players.iter_mut() {
+        history.0.push_front(pos.0);
+        history.0.truncate(HISTORY_TICKS);
+    }
+}
+
+/// Distance from the attacker's eyes to the closest point of the victim's hitbox.
+fn reach_distance(attacker: DVec3, victim: DVec3) -> f64 {
+    let eyes = attacker + DVec3::new(0.0, EYE_HEIGHT, 0.0);
+    let half_width = PLAYER_WIDTH / 2.0;
+    let min = victim - DVec3::new(half_width, 0.0, half_width);
+    let max = victim + DVec3::new(half_width, PLAYER_HEIGHT, half_width);
+    eyes.distance(eyes.clamp(min, max))
+}
+
+/// How far the attack cooldown of `weapon` recovered after `ticks`, from 0 to 1.
+fn cooldown_progress(weapon: ItemKind, ticks: i64) -> f32 {
+    let period = 20.0 / attack_speed(weapon);
+    ((ticks as f32 + 0.5) / period).clamp(0.0, 1.0)
+}
+
+/// Whether a player at `pos` looking along `look` faces `source`.
+fn faces(pos: DVec3, look: &Look, source: DVec3) -> bool {
+    let yaw = (look.yaw as f64).to_radians();
+    let view = DVec2::new(-yaw.sin(), yaw.cos());
+    (source.xz() - pos.xz()).dot(view) > 0.0
+}
+
+/// Whether `attacker` is allowed to hurt `victim`.
+fn can_hurt(attacker: &PlayerGameState, victim: &PlayerGameState) -> bool {
+    attacker.game_id.is_some()
+        && attacker.game_id == victim.game_id
+        && attacker.team != victim.team
+}
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+struct MeleeQuery {
+    entity: Entity,
+    state: &'static mut CombatState,
+    gamestate: &'static PlayerGameState,
+    username: &'static Username,
+    pos: &'static Position,
+    old_pos: &'static OldPosition,
+    history: &'static PositionHistory,
+    ping: &'static Ping,
+    on_ground: &'static OnGround,
+    inv: &'static Inventory,
+    held_item: &'static HeldItem,
+    effects: Option<&'static StatusEffects>,
+    hunger: Option<&'static Hunger>,
+}
+
+fn handle_melee(
+    mut players: Query<MeleeQuery>,
+    games: Query<&Knockback>,
+    mut sprinting: MessageReader<SprintMessage>,
+    mut interact_entity: MessageReader<InteractEntityMessage>,
+    mut packets: MessageReader<PacketMessage>,
+    mut damage: MessageWriter<DamageMessage>,
+    mut sweeps: MessageWriter<SweepMessage>,
+    server: Res<Server>,
+    settings: Res<CombatSettings>,
+) {
+    let tick = server.current_tick();
+    for &SprintMessage { client, state } in sprinting.read() {
+        let Ok(mut player) = players.get_mut(client) else {
+            continue;
+        };
+        match state {
+            // Hungry players can't sprint
+            SprintState::Start if player.hunger.is_none_or(|hunger| hunger.can_sprint()) => {
+                player.state.sprinting = true;
+                player.state.has_bonus_knockback = true;
+            }
+            SprintState::Start => {}
+            SprintState::Stop => {
+                player.state.sprinting = false;
+                player.state.sprint_stop_tick = tick;
+            }
+        }
+    }
+
+    for &InteractEntityMessage {
+        client: attacker_entity,
+        entity: victim_entity,
+        interact,
+        ..
+    } in interact_entity.read()
+    {
+        if interact != EntityInteraction::Attack {
+            continue;
+        }
+        let Ok([mut attacker, mut victim]) = players.get_many_mut([attacker_entity, victim_entity])
+        else {
+            continue;
+        };
+        if !can_hurt(attacker.gamestate, victim.gamestate) {
+            continue;
+        }
+
+        // Every attack restarts the cooldown, even ones that are rejected
+        let weapon = attacker.inv.slot(attacker.held_item.slot()).item;
+        let cooldown = match settings.rules {
+            CombatRules::Legacy => 1.0,
+            CombatRules::Modern => cooldown_progress(weapon, tick - attacker.state.last_swing_tick),
+        };
+        attacker.state.last_swing_tick = tick;
+        let charged = cooldown > CHARGED_COOLDOWN;
+
+        // The attacker saw the victim where they were one ping ago
+        let rewind = (attacker.ping.0.max(0) as usize)
+            .div_ceil(50)
+            .min(HISTORY_TICKS - 1);
+        let victim_pos = victim.history.rewind(rewind).unwrap_or(victim.pos.0);
+        // The ping is only an estimate, so being in reach at either position is enough
+        let distance = reach_distance(attacker.pos.0, victim_pos)
+            .min(reach_distance(attacker.pos.0, victim.pos.0));
+        if distance > settings.reach {
+            attacker.state.rejected_hits += 1;
+            if tick - attacker.state.rejected_log_tick >= REJECTED_LOG_TICKS {
+                eprintln!(
+                    "Rejected {} hits from {}, last on {}: reach {:.2} > {:.2} (ping {}ms, \
+                     rewound {} ticks)",
+                    attacker.state.rejected_hits,
+                    attacker.username.0,
+                    victim.username.0,
+                    distance,
+                    settings.reach,
+                    attacker.ping.0,
+                    rewind,
+                );
+                attacker.state.rejected_hits = 0;
+                attacker.state.rejected_log_tick = tick;
+            }
+            continue;
+        }
+
+        let since_hit = tick - victim.state.last_attacked_tick;
+        if since_hit < settings.invulnerable_ticks {
+            continue;
+        }
+        victim.state.last_attacked_tick = tick;
+
+        let profile = victim
+            .gamestate
+            .game_id
+            .and_then(|game_id| games.get(game_id).ok())
+            .map_or(&settings.knockback, |knockback| &knockback.0);
+
+        // Hitting back ends the combo the attacker was caught in, and so does a pause in the hits
+        let combo = if victim.state.last_attacker == Some(attacker_entity)
+            && since_hit <= settings.invulnerable_ticks + COMBO_GAP_TICKS
+        {
+            victim.state.combo
+        } else {
+            0
+        };
+        victim.state.combo = combo + 1;
+        attacker.state.combo = 0;
+
+        // Clients stop sprinting right after a sprint hit, and the stop is read before the hit
+        // when both arrive in the same tick
+        let sprint_hit = charged
+            && attacker.state.has_bonus_knockback
+            && (attacker.state.sprinting || attacker.state.sprint_stop_tick == tick);
+        let (mut knockback_xz, mut knockback_y) = (profile.horizontal, profile.vertical);
+        if sprint_hit {
+            knockback_xz += profile.sprint_horizontal;
+            knockback_y += profile.sprint_vertical;
+            attacker.state.has_bonus_knockback = false;
+        }
+        let mut multiplier = (1.0 - profile.combo_reduction * combo as f32).max(0.0);
+        if !victim.on_ground.0 {
+            multiplier *= profile.air_multiplier;
+        }
+        knockback_xz *= multiplier;
+        knockback_y *= multiplier;
+
+        let dir = (victim.pos.0.xz() - attacker.pos.0.xz()).normalize().as_vec2();
+
+        let mut dmg = match settings.melee_damage {
+            MeleeDamage::None => 0.0,
+            MeleeDamage::Fixed(damage) => damage,
+            MeleeDamage::Weapon => match settings.rules {
+                CombatRules::Legacy => item_dmg(weapon),
+                CombatRules::Modern => modern_item_dmg(weapon),
+            },
+        };
+        if dmg > 0.0
+            && let Some(amplifier) = attacker
+                .effects
+                .and_then(|effects| effects.amplifier(StatusEffect::Strength))
+        {
+            let level = amplifier as f32 + 1.0;
+            match settings.rules {
+                CombatRules::Legacy => dmg *= 1.0 + 1.3 * level,
+                CombatRules::Modern => dmg += 3.0 * level,
+            }
+        }
+        dmg *= 0.2 + cooldown * cooldown * 0.8;
+        let critical = settings.critical_hits
+            && dmg > 0.0
+            && !attacker.on_ground.0
+            && attacker.pos.0.y < attacker.old_pos.get().y
+            // 1.9+ crits need a charged attack and can't be sprint hits
+            && (settings.rules == CombatRules::Legacy || (charged && !attacker.state.sprinting));
+        if critical {
+            dmg *= CRIT_MULTIPLIER;
+        }
+        let sweep = settings.rules == CombatRules::Modern
+            && charged
+            && is_sword(weapon)
+            && attacker.on_ground.0
+            && !sprint_hit
+            && !critical;
+
+        damage.write(DamageMessage {
+            attacker: attacker_entity,
+            victim: victim_entity,
+            damage: dmg,
+            knockback: Vec3::new(dir.x * knockback_xz, knockback_y, dir.y * knockback_xz),
+            projectile: false,
+            critical,
+        });
+
+        if !sweep {
+            continue;
+        }
+        sweeps.write(SweepMessage(attacker_entity));
+        let attacker_pos = attacker.pos.0;
+        let (game_id, team) = (attacker.gamestate.game_id, attacker.gamestate.team);
+        let victim_pos = victim.pos.0;
+        for mut target in players.iter_mut() {
+            if target.entity == attacker_entity
+                || target.entity == victim_entity
+                || target.gamestate.game_id != game_id
+                || target.gamestate.team == team
+                || target.gamestate.eliminated
+            {
+                continue;
+            }
+            // Vanilla hits everyone whose hitbox touches the victim's, grown by a block to the
+            // sides, within 3 blocks of the attacker
+            let offset = target.pos.0 - victim_pos;
+            let touching = offset.x.abs() < PLAYER_WIDTH + 1.0
+                && offset.z.abs() < PLAYER_WIDTH + 1.0
+                && offset.y.abs() < PLAYER_HEIGHT + 0.25;
+            if !touching
+                || target.pos.0.distance(attacker_pos) >= 3.0
+                || tick - target.state.last_attacked_tick < settings.invulnerable_ticks
+            {
+                continue;
+            }
+            target.state.last_attacked_tick = tick;
+
+            let dir = (target.pos.0.xz() - attacker_pos.xz()).normalize().as_vec2();
+            let (knockback_xz, knockback_y) = (
+                profile.horizontal * SWEEP_KNOCKBACK,
+                profile.vertical * SWEEP_KNOCKBACK,
+            );
+            damage.write(DamageMessage {
+                attacker: attacker_entity,
+                victim: target.entity,
+                damage: SWEEP_DAMAGE,
+                knockback: Vec3::new(dir.x * knockback_xz, knockback_y, dir.y * knockback_xz),
+                projectile: false,
+                critical: false,
+            });
+        }
+    }
+
+    // Read after the attacks, since clients send the swing of an attack right after it
+    for packet in packets.read() {
+        if packet.decode::<SwingC2s>().is_some()
+            && let Ok(mut player) = players.get_mut(packet.client)
+        {
+            player.state.last_swing_tick = tick;
+        }
+    }
+}
+
+/// Sent for every sweep attack, to play its sound and particles
+#[derive(Message)]
+struct SweepMessage(Entity);
+
+fn play_sweep_effects(
+    mut clients: Query<(&mut Client, &PlayerGameState)>,
+    players: Query<(&Position, &Look, &PlayerGameState)>,
+    mut sweeps: MessageReader<SweepMessage>,
+) {
+    for SweepMessage(attacker) in sweeps.read() {
+        let Ok((pos, look, attacker_state)) = players.get(*attacker) else {
+            continue;
+        };
+        let yaw = (look.yaw as f64).to_radians();
+        let pos = pos.0 + DVec3::new(-yaw.sin(), PLAYER_HEIGHT / 2.0, yaw.cos());
+        for (mut client, gamestate) in clients.iter_mut() {
+            if gamestate.game_id != attacker_state.game_id {
+                continue;
+            }
+            client.play_particle(&Particle::SweepAttack, true, false, pos, Vec3::ZERO, 0.0, 1);
+            client.play_sound(
+                Sound::EntityPlayerAttackSweep,
+                SoundCategory::Player,
+                pos,
+                1.0,
+                1.0,
+            );
+        }
+    }
+}
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub struct CombatQuery {
+    entity: Entity,
+    client: &'static mut Client,
+    id: &'static EntityId,
+    pos: &'static Position,
+    old_pos: &'static OldPosition,
+    state: &'static mut CombatState,
+    gamestate: &'static PlayerGameState,
+    health: &'static mut Health,
+    absorption: &'static mut Absorption,
+    inv: &'static Inventory,
+    held_item: &'static HeldItem,
+    look: &'static Look,
+    effects: Option<&'static StatusEffects>,
+}
+
+/// Starts blocking when a player uses a sword or raises a shield, until they release right click
+/// or, for swords, stop sending use packets.
+fn track_blocking(
+    mut players: Query<(&mut CombatState, &Inventory, &HeldItem)>,
+    mut interact_item: MessageReader<InteractItemMessage>,
+    mut packets: MessageReader<PacketMessage>,
+    server: Res<Server>,
+    settings: Res<CombatSettings>,
+) {
+    let tick = server.current_tick();
+    for message in interact_item.read() {
+        let Ok((mut state, inv, held_item)) = players.get_mut(message.client) else {
+            continue;
+        };
+        let item = inv
+            .slot(match message.hand {
+                Hand::Main => held_item.slot(),
+                Hand::Off => PlayerInventory::SLOT_OFFHAND,
+            })
+            .item;
+        if settings.sword_block.is_some() && message.hand == Hand::Main && is_sword(item) {
+            state.block_tick = Some(tick);
+        } else if settings.rules == CombatRules::Modern
+            && item == ItemKind::Shield
+            && tick >= state.shield_disabled_until
+        {
+            state.shield_tick.get_or_insert(tick);
+        }
+    }
+    for packet in packets.read() {
+        if let Some(pkt) = packet.decode::<PlayerActionC2s>()
+            && pkt.action == PlayerAction::ReleaseUseItem
+            && let Ok((mut state, _, _)) = players.get_mut(packet.client)
+        {
+            state.block_tick = None;
+            state.shield_tick = None;
+        }
+    }
+}
+
+/// Whether the victim's raised shield stops the damage, disabling the shield if it was hit with an
+/// axe.
+fn shield_blocks(
+    attacker: &mut CombatQueryItem,
+    victim: &mut CombatQueryItem,
+    projectile: bool,
+    tick: i64,
+) -> bool {
+    let raised = victim
+        .state
+        .shield_tick
+        .is_some_and(|since| tick - since >= SHIELD_DELAY_TICKS);
+    let holds_shield = [victim.held_item.slot(), PlayerInventory::SLOT_OFFHAND]
+        .into_iter()
+        .any(|slot| victim.inv.slot(slot).item == ItemKind::Shield);
+    // Arrows are treated as coming from the shooter
+    if !raised || !holds_shield || !faces(victim.pos.0, victim.look, attacker.pos.0) {
+        return false;
+    }
+
+    let sound = if !projectile && is_axe(attacker.inv.slot(attacker.held_item.slot()).item) {
+        victim.state.shield_tick = None;
+        victim.state.shield_disabled_until = tick + SHIELD_DISABLE_TICKS;
+        // Also makes the client lower the shield
+        victim.client.write_packet(&CooldownUpdateS2c {
+            item_id: VarInt(ItemKind::Shield.to_raw() as i32),
+            cooldown_ticks: VarInt(SHIELD_DISABLE_TICKS as i32),
+        });
+        Sound::ItemShieldBreak
+    } else {
+        Sound::ItemShieldBlock
+    };
+    for client in [&mut attacker.client, &mut victim.client] {
+        client.play_sound(sound, SoundCategory::Player, victim.pos.0, 1.0, 1.0);
+    }
+    true
+}
+
+pub fn apply_damage(
+    mut players: Query<CombatQuery>,
+    games: Query<&Knockback>,
+    mut damage: MessageReader<DamageMessage>,
+    mut hits: MessageWriter<HitMessage>,
+    mut eliminations: MessageWriter<EliminationMessage>,
+    mut deaths: MessageWriter<DeathMessage>,
+    reconnects: Option<Res<Reconnects>>,
+    server: Res<Server>,
+    settings: Res<CombatSettings>,
+) {
+    let tick = server.current_tick();
+    for event in damage.read() {
+        let Ok([mut attacker, mut victim]) = players.get_many_mut([event.attacker, event.victim])
+        else {
+            continue;
+        };
+        if !can_hurt(attacker.gamestate, victim.gamestate) || victim.gamestate.eliminated {
+            continue;
+        }
+        // Nobody takes damage while the game waits for a player to reconnect
+        if let (Some(reconnects), Some(game_id)) = (&reconnects, victim.gamestate.game_id)
+            && reconnects.is_paused(game_id)
+        {
+            continue;
+        }
+        if settings.rules == CombatRules::Modern
+            && shield_blocks(&mut attacker, &mut victim, event.projectile, tick)
+        {
+            continue;
+        }
+
+        let profile = victim
+            .gamestate
+            .game_id
+            .and_then(|game_id| games.get(game_id).ok())
+            .map_or(&settings.knockback, |knockback| &knockback.0);
+        let mut velocity = event.knockback;
+        if let Some(friction) = profile.friction {
+            // Positions are sent every tick, so this is the victim's velocity in blocks per tick
+            let momentum = (victim.pos.0 - victim.old_pos.get()).as_vec3() * 20.0;
+            velocity += momentum / friction;
+        }
+        if let Some(limit) = profile.vertical_limit {
+            velocity.y = velocity.y.min(limit);
+        }
+        victim.client.set_velocity(velocity);
+
+        victim.client.play_sound(
+            Sound::EntityPlayerHurt,
+            SoundCategory::Player,
+            victim.pos.0,
+            1.0,
+            1.0,
+        );
+        victim.client.write_packet(&HurtAnimationS2c {
+            entity_id: VarInt(0),
+            yaw: 0.0,
+        });
+        attacker.client.play_sound(
+            Sound::EntityPlayerHurt,
+            SoundCategory::Player,
+            victim.pos.0,
+            1.0,
+            1.0,
+        );
+        attacker.client.write_packet(&HurtAnimationS2c {
+            entity_id: VarInt(victim.id.get()),
+            yaw: 0.0,
+        });
+        if event.projectile {
+            attacker.client.play_sound(
+                Sound::EntityArrowHitPlayer,
+                SoundCategory::Player,
+                attacker.pos.0,
+                1.0,
+                1.0,
+            );
+        }
+        if event.critical {
+            let pos = victim.pos.0 + DVec3::new(0.0, PLAYER_HEIGHT / 2.0, 0.0);
+            for client in [&mut attacker.client, &mut victim.client] {
+                client.play_particle(
+                    &Particle::Crit,
+                    true,
+                    false,
+                    pos,
+                    Vec3::new(0.3, 0.5, 0.3),
+                    0.3,
+                    15,
+                );
+                client.play_sound(
+                    Sound::EntityPlayerAttackCrit,
+                    SoundCategory::Player,
+                    victim.pos.0,
+                    1.0,
+                    1.0,
+                );
+            }
+        }
+
+        victim.state.last_attacker = Some(attacker.entity);
+
+        let blocking = victim.state.block_tick.is_some_and(|block_tick| {
+            tick - block_tick <= BLOCK_TIMEOUT_TICKS
+                && is_sword(victim.inv.slot(victim.held_item.slot()).item)
+        });
+        let blocked = match settings.sword_block {
+            Some(multiplier) if blocking => multiplier,
+            _ => 1.0,
+        };
+
+        let mut dmg = calc_dmg(
+            event.damage * blocked,
+            victim.inv.slot(5).item,
+            victim.inv.slot(6).item,
+            victim.inv.slot(7).item,
+            victim.inv.slot(8).item,
+        );
+        if let Some(amplifier) = victim
+            .effects
+            .and_then(|effects| effects.amplifier(StatusEffect::Resistance))
+        {
+            dmg *= (1.0 - 0.2 * (amplifier as f32 + 1.0)).max(0.0);
+        }
+        let absorbed = dmg.min(victim.absorption.0);
+        victim.absorption.0 -= absorbed;
+        let dmg_to_health = dmg - absorbed;
+        if victim.health.0 <= dmg_to_health {
+            match settings.on_death {
+                OnDeath::DeathEvent => {
+                    deaths.write(DeathMessage(victim.entity, true));
+                }
+                OnDeath::Eliminate => {
+                    if let Some(game_id) = victim.gamestate.game_id {
+                        eliminations.write(EliminationMessage {
+                            game_id,
+                            player: victim.entity,
+                        });
+                    }
+                }
+            }
+        } else {
+            victim.health.0 -= dmg_to_health;
+        }
+
+        hits.write(HitMessage {
+            attacker: event.attacker,
+            victim: event.victim,
+            damage: dmg,
+            projectile: event.projectile,
+        });
+    }
+}
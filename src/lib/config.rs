@@ -47,12 +47,57 @@ pub struct WorldValue {
     pub x_chunks: [i32; 2],
     pub z_chunks: [i32; 2],
     pub spawns: Vec<SpawnValue>,
+    /// Name shown in map votes, defaults to the map's path
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Relative chance of the map being picked when nobody voted. A weight of 0 disables the map.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Knockback profile used on the map instead of the mode's default
+    #[serde(default)]
+    pub knockback: Option<String>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl WorldValue {
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.path)
+    }
+
+    /// Returns the spawn for `team`. Maps are checked on startup to have one for every team.
+    pub fn team_spawn(&self, team: u8) -> &SpawnValue {
+        &self.spawns[team as usize]
+    }
+
+    /// Returns where the `slot`th player of `team` spawns. Maps are checked on startup to have a
+    /// spot for every player of a team.
+    pub fn player_spawn(&self, team: u8, slot: u8) -> PlayerSpawn {
+        let spawn = self.team_spawn(team);
+        let pos = match slot {
+            0 => spawn.pos,
+            _ => spawn.teammates[slot as usize - 1],
+        };
+        PlayerSpawn { pos, rot: spawn.rot }
+    }
 }
 
 #[derive(Deserialize)]
 pub struct SpawnValue {
     pub pos: [f64; 3],
     pub rot: [f32; 2],
+    /// Where the other players of the team spawn in team modes, in order, facing the same way
+    #[serde(default)]
+    pub teammates: Vec<[f64; 3]>,
+}
+
+/// Where a single player spawns
+#[derive(Clone, Copy)]
+pub struct PlayerSpawn {
+    pub pos: [f64; 3],
+    pub rot: [f32; 2],
 }
 
 #[derive(Resource)]
@@ -66,8 +111,10 @@ pub struct ConfigLoaderPlugin<T: DeserializeOwned> {
 
 impl<T: Resource + DeserializeOwned + Sync + Send + 'static> Plugin for ConfigLoaderPlugin<T> {
     fn build(&self, app: &mut App) {
-        let data = std::fs::read_to_string(self.path.join("config.json")).unwrap();
-        let config = serde_json::from_str::<T>(&data).unwrap();
+        let path = self.path.join("config.json");
+        let data = std::fs::read_to_string(&path).unwrap();
+        let config = serde_json::from_str::<T>(&data)
+            .unwrap_or_else(|err| panic!("Invalid {}: {}", path.display(), err));
 
         app.insert_resource(ServerSettings {
             compression_threshold: CompressionThreshold(-1),
@@ -95,3 +142,32 @@ impl<T: Resource + DeserializeOwned + Sync + Send + 'static> Plugin for ConfigLo
         .insert_resource(DataPath(self.path.clone()));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn teammates_spawn_at_their_own_spot() {
+        let world: WorldValue = serde_json::from_str(
+            r#"{
+                "path": "map",
+                "x_chunks": [0, 0],
+                "z_chunks": [0, 0],
+                "spawns": [
+                    {"pos": [0.5, 64.0, 0.5], "rot": [0.0, 0.0], "teammates": [[2.5, 64.0, 0.5]]},
+                    {"pos": [0.5, 64.0, 20.5], "rot": [180.0, 0.0]}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let first = world.player_spawn(0, 0);
+        let second = world.player_spawn(0, 1);
+        assert_eq!(first.pos, [0.5, 64.0, 0.5]);
+        assert_eq!(second.pos, [2.5, 64.0, 0.5]);
+        assert_eq!(second.rot, first.rot);
+        // Spawns without teammates are enough for 1v1
+        assert_eq!(world.player_spawn(1, 0).pos, [0.5, 64.0, 20.5]);
+    }
+}
@@ -29,6 +29,95 @@ pub fn item_dmg(item: ItemKind) -> f32 {
     }
 }
 
+// Uses 1.9+ damage
+pub fn modern_item_dmg(item: ItemKind) -> f32 {
+    match item {
+        ItemKind::WoodenSword => 4.0,
+        ItemKind::GoldenSword => 4.0,
+        ItemKind::StoneSword => 5.0,
+        ItemKind::IronSword => 6.0,
+        ItemKind::DiamondSword => 7.0,
+        ItemKind::NetheriteSword => 8.0,
+        ItemKind::WoodenAxe => 7.0,
+        ItemKind::GoldenAxe => 7.0,
+        ItemKind::StoneAxe => 9.0,
+        ItemKind::IronAxe => 9.0,
+        ItemKind::DiamondAxe => 9.0,
+        ItemKind::NetheriteAxe => 10.0,
+        ItemKind::WoodenPickaxe => 2.0,
+        ItemKind::GoldenPickaxe => 2.0,
+        ItemKind::StonePickaxe => 3.0,
+        ItemKind::IronPickaxe => 4.0,
+        ItemKind::DiamondPickaxe => 5.0,
+        ItemKind::NetheritePickaxe => 6.0,
+        ItemKind::WoodenShovel => 2.5,
+        ItemKind::GoldenShovel => 2.5,
+        ItemKind::StoneShovel => 3.5,
+        ItemKind::IronShovel => 4.5,
+        ItemKind::DiamondShovel => 5.5,
+        ItemKind::NetheriteShovel => 6.5,
+        ItemKind::Trident => 9.0,
+        _ => 1.0,
+    }
+}
+
+/// Attacks per second that deal full damage with the item, used by the 1.9+ attack cooldown
+pub fn attack_speed(item: ItemKind) -> f32 {
+    match item {
+        ItemKind::WoodenSword
+        | ItemKind::GoldenSword
+        | ItemKind::StoneSword
+        | ItemKind::IronSword
+        | ItemKind::DiamondSword
+        | ItemKind::NetheriteSword => 1.6,
+        ItemKind::WoodenAxe | ItemKind::StoneAxe => 0.8,
+        ItemKind::IronAxe => 0.9,
+        ItemKind::GoldenAxe | ItemKind::DiamondAxe | ItemKind::NetheriteAxe => 1.0,
+        ItemKind::WoodenPickaxe
+        | ItemKind::GoldenPickaxe
+        | ItemKind::StonePickaxe
+        | ItemKind::IronPickaxe
+        | ItemKind::DiamondPickaxe
+        | ItemKind::NetheritePickaxe => 1.2,
+        ItemKind::WoodenShovel
+        | ItemKind::GoldenShovel
+        | ItemKind::StoneShovel
+        | ItemKind::IronShovel
+        | ItemKind::DiamondShovel
+        | ItemKind::NetheriteShovel => 1.0,
+        ItemKind::WoodenHoe | ItemKind::GoldenHoe => 1.0,
+        ItemKind::StoneHoe => 2.0,
+        ItemKind::IronHoe => 3.0,
+        ItemKind::DiamondHoe | ItemKind::NetheriteHoe => 4.0,
+        ItemKind::Trident => 1.1,
+        _ => 4.0,
+    }
+}
+
+pub fn is_axe(item: ItemKind) -> bool {
+    matches!(
+        item,
+        ItemKind::WoodenAxe
+            | ItemKind::GoldenAxe
+            | ItemKind::StoneAxe
+            | ItemKind::IronAxe
+            | ItemKind::DiamondAxe
+            | ItemKind::NetheriteAxe
+    )
+}
+
+pub fn is_sword(item: ItemKind) -> bool {
+    matches!(
+        item,
+        ItemKind::WoodenSword
+            | ItemKind::GoldenSword
+            | ItemKind::StoneSword
+            | ItemKind::IronSword
+            | ItemKind::DiamondSword
+            | ItemKind::NetheriteSword
+    )
+}
+
 pub fn armor_points(item: ItemKind) -> f32 {
     match item {
         ItemKind::LeatherHelmet => 1.0,
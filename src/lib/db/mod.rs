@@ -2,26 +2,129 @@
 pub mod models;
 pub mod schema;
 
+use bigdecimal::BigDecimal;
 use chunkedge::prelude::*;
 use diesel::prelude::*;
 use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+use std::time::Duration;
 
+/// How long to wait before trying to connect to the database again
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+type Job = Box<dyn FnOnce(&mut PgConnection) + Send>;
+
+/// Connection to the network's database. Queries run on a thread of their own, so that a slow
+/// query never holds up a tick. Without a configured database, jobs are dropped.
+#[derive(Resource)]
+pub struct Database {
+    jobs: Option<Sender<Job>>,
+}
+
+impl Database {
+    /// Whether a database is configured, so that queries get results.
+    pub fn is_enabled(&self) -> bool {
+        self.jobs.is_some()
+    }
+
+    /// Queues `job` to run on the database thread.
+    pub fn run(&self, job: impl FnOnce(&mut PgConnection) + Send + 'static) {
+        // The thread only stops once the resource is dropped
+        if let Some(jobs) = &self.jobs {
+            let _ = jobs.send(Box::new(job));
+        }
+    }
+
+    /// Queues `query` to run on the database thread and sends its result back to `results`.
+    pub fn query<T: Send + 'static>(
+        &self,
+        results: &DbResults<T>,
+        query: impl FnOnce(&mut PgConnection) -> T + Send + 'static,
+    ) {
+        let sender = results.sender.clone();
+        self.run(move |conn| {
+            let _ = sender.send(query(conn));
+        });
+    }
+}
+
+/// Results of queries that finished on the database thread, waiting to be read by a system
 #[derive(Resource)]
-pub struct Database(pub Mutex<PgConnection>);
+pub struct DbResults<T> {
+    sender: Sender<T>,
+    receiver: Mutex<Receiver<T>>,
+}
+
+impl<T> Default for DbResults<T> {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+}
+
+impl<T> DbResults<T> {
+    /// Takes every result that has arrived so far.
+    pub fn drain(&self) -> Vec<T> {
+        self.receiver.lock().unwrap().try_iter().collect()
+    }
+}
+
+/// Makes sure the player has a row in the players table, which every other table refers to.
+pub fn ensure_player(conn: &mut PgConnection, uuid: &BigDecimal) -> QueryResult<usize> {
+    diesel::insert_into(schema::players::table)
+        .values(schema::players::uuid.eq(uuid))
+        .on_conflict_do_nothing()
+        .execute(conn)
+}
 
 pub struct DatabasePlugin {
-    connection_string: &'static str,
+    connection_string: String,
 }
 
 impl DatabasePlugin {
-    pub fn new(connection_string: &'static str) -> Self {
-        Self { connection_string }
+    pub fn new(connection_string: impl Into<String>) -> Self {
+        Self {
+            connection_string: connection_string.into(),
+        }
     }
 }
 
 impl Plugin for DatabasePlugin {
     fn build(&self, app: &mut App) {
-        let db = PgConnection::establish(self.connection_string).unwrap();
-        app.insert_resource(Database(Mutex::new(db)));
+        if self.connection_string.is_empty() {
+            eprintln!(
+                "No database url is configured, so nothing will be saved. Set database.url or \
+                 MINIBIT_DATABASE_URL to keep ratings and kit layouts."
+            );
+            app.insert_resource(Database { jobs: None });
+            return;
+        }
+
+        // Jobs queue up until the database accepts connections, e.g. while it is still starting
+        let connection_string = self.connection_string.clone();
+        let (jobs, receiver) = channel::<Job>();
+        thread::spawn(move || {
+            let mut conn = loop {
+                match PgConnection::establish(&connection_string) {
+                    Ok(conn) => break conn,
+                    Err(e) => {
+                        eprintln!(
+                            "Couldn't connect to the database, retrying in {}s: {}",
+                            RETRY_DELAY.as_secs(),
+                            e
+                        );
+                        thread::sleep(RETRY_DELAY);
+                    }
+                }
+            };
+            for job in receiver {
+                job(&mut conn);
+            }
+        });
+        app.insert_resource(Database { jobs: Some(jobs) });
     }
 }
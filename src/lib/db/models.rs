@@ -60,7 +60,7 @@ pub struct MinigameStat {
     pub stat_value: BigDecimal,
 }
 
-#[derive(Queryable, Selectable, Debug)]
+#[derive(Queryable, Selectable, Insertable, Debug)]
 #[diesel(table_name = super::schema::minigame_inventories)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct MiniGameInventory {
@@ -0,0 +1,268 @@
+#![allow(clippy::type_complexity)]
+#![allow(clippy::too_many_arguments)]
+
+use super::*;
+use super::matchmaking::Unranked;
+use crate::scopes::ScopePlugin;
+use chunkedge::{
+    command::{AddCommand, handler::CommandResultMessage},
+    command_macros::Command,
+};
+
+/// How long a challenge can be accepted for
+const CHALLENGE_EXPIRY_SECS: i64 = 60;
+
+#[derive(Command, Debug, Clone)]
+#[paths("duel")]
+#[scopes("minibit.commands.all.duel")]
+enum DuelCommand {
+    #[paths("accept {player}")]
+    Accept { player: String },
+    #[paths("deny {player}")]
+    Deny { player: String },
+    #[paths("{player} {map?}")]
+    Challenge { player: String, map: Option<String> },
+}
+
+struct Challenge {
+    from: Entity,
+    to: Entity,
+    map: Option<usize>,
+    expires: i64,
+}
+
+#[derive(Resource, Default)]
+struct Challenges(Vec<Challenge>);
+
+pub struct ChallengePlugin<T: Resource + DuelsConfig> {
+    pub phantom: PhantomData<T>,
+}
+
+impl<T: Resource + DuelsConfig> Plugin for ChallengePlugin<T> {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<ScopePlugin>() {
+            app.add_plugins(ScopePlugin);
+        }
+        app.insert_resource(Challenges::default())
+            .add_command::<DuelCommand>()
+            .add_systems(Update, (handle_duel_command::<T>, expire_challenges));
+    }
+}
+
+fn handle_duel_command<T: Resource + DuelsConfig>(
+    mut clients: Query<&mut Client>,
+    usernames: Query<(Entity, &Username), With<Client>>,
+    mut commands_result: MessageReader<CommandResultMessage<DuelCommand>>,
+    mut start_game: MessageWriter<StartGameMessage>,
+    mut commands: Commands,
+    mut challenges: ResMut<Challenges>,
+    mut globals: ResMut<ServerGlobals>,
+    server: Res<Server>,
+    settings: Res<GameSettings>,
+    config: Res<T>,
+) {
+    for message in commands_result.read() {
+        let executor = message.executor;
+        let Ok((_, executor_name)) = usernames.get(executor) else {
+            continue;
+        };
+        let executor_name = executor_name.0.clone();
+
+        let (DuelCommand::Accept { player }
+        | DuelCommand::Deny { player }
+        | DuelCommand::Challenge { player, .. }) = &message.result;
+        let Some((target, target_name)) = usernames
+            .iter()
+            .find(|(_, username)| username.0.eq_ignore_ascii_case(player))
+            .map(|(entity, username)| (entity, username.0.clone()))
+        else {
+            send(&mut clients, executor, "That player isn't online.".color(Color::RED));
+            continue;
+        };
+
+        match &message.result {
+            DuelCommand::Challenge { map, .. } => {
+                if settings.players_per_game() != 2 {
+                    send(
+                        &mut clients,
+                        executor,
+                        "Duels can only be sent in 1v1 modes.".color(Color::RED),
+                    );
+                    continue;
+                }
+                if target == executor {
+                    send(&mut clients, executor, "You can't duel yourself.".color(Color::RED));
+                    continue;
+                }
+                if !globals.queue.contains(&executor) || !globals.queue.contains(&target) {
+                    send(
+                        &mut clients,
+                        executor,
+                        "You both need to be out of a game to duel.".color(Color::RED),
+                    );
+                    continue;
+                }
+                let map = match map {
+                    Some(name) => match find_map(config.worlds(), name) {
+                        Some(map) => Some(map),
+                        None => {
+                            send(&mut clients, executor, "Unknown map.".color(Color::RED));
+                            continue;
+                        }
+                    },
+                    None => None,
+                };
+
+                challenges
+                    .0
+                    .retain(|challenge| !(challenge.from == executor && challenge.to == target));
+                challenges.0.push(Challenge {
+                    from: executor,
+                    to: target,
+                    map,
+                    expires: server.current_tick() + CHALLENGE_EXPIRY_SECS * 20,
+                });
+
+                let map_text = match map {
+                    Some(map) => " on ".color(Color::GRAY)
+                        + config.worlds()[map].display_name().to_string().color(Color::GOLD),
+                    None => Text::from(String::new()),
+                };
+                send(
+                    &mut clients,
+                    executor,
+                    "Sent a duel request to ".color(Color::GRAY)
+                        + target_name.color(Color::GOLD)
+                        + map_text.clone()
+                        + ".".color(Color::GRAY),
+                );
+                send(
+                    &mut clients,
+                    target,
+                    executor_name.clone().color(Color::GOLD)
+                        + " challenged you to a duel".color(Color::GRAY)
+                        + map_text
+                        + "! ".color(Color::GRAY)
+                        + "[Accept]"
+                            .color(Color::GREEN)
+                            .bold()
+                            .on_click_run_command(format!("/duel accept {}", executor_name))
+                        + " "
+                        + "[Deny]"
+                            .color(Color::RED)
+                            .bold()
+                            .on_click_run_command(format!("/duel deny {}", executor_name)),
+                );
+            }
+            DuelCommand::Accept { .. } => {
+                let Some(i) = challenges
+                    .0
+                    .iter()
+                    .position(|challenge| challenge.from == target && challenge.to == executor)
+                else {
+                    send(
+                        &mut clients,
+                        executor,
+                        "You don't have a duel request from that player.".color(Color::RED),
+                    );
+                    continue;
+                };
+                let challenge = challenges.0.remove(i);
+                if !globals.queue.contains(&executor) || !globals.queue.contains(&target) {
+                    send(
+                        &mut clients,
+                        executor,
+                        "You both need to be out of a game to duel.".color(Color::RED),
+                    );
+                    continue;
+                }
+
+                challenges.0.retain(|challenge| {
+                    ![challenge.from, challenge.to]
+                        .iter()
+                        .any(|entity| *entity == executor || *entity == target)
+                });
+                globals
+                    .queue
+                    .retain(|entity| *entity != executor && *entity != target);
+
+                let game_id = spawn_game(
+                    &mut commands,
+                    &server,
+                    &settings,
+                    vec![challenge.from, challenge.to],
+                    challenge.map,
+                );
+                commands.entity(game_id).insert(Unranked);
+                start_game.write(StartGameMessage(game_id));
+            }
+            DuelCommand::Deny { .. } => {
+                let before = challenges.0.len();
+                challenges
+                    .0
+                    .retain(|challenge| !(challenge.from == target && challenge.to == executor));
+                if challenges.0.len() == before {
+                    send(
+                        &mut clients,
+                        executor,
+                        "You don't have a duel request from that player.".color(Color::RED),
+                    );
+                    continue;
+                }
+                send(
+                    &mut clients,
+                    executor,
+                    "Denied the duel request.".color(Color::GRAY),
+                );
+                send(
+                    &mut clients,
+                    target,
+                    executor_name.color(Color::GOLD)
+                        + " denied your duel request.".color(Color::GRAY),
+                );
+            }
+        }
+    }
+}
+
+fn expire_challenges(
+    mut clients: Query<&mut Client>,
+    usernames: Query<&Username>,
+    mut challenges: ResMut<Challenges>,
+    server: Res<Server>,
+) {
+    let tick = server.current_tick();
+    challenges.0.retain(|challenge| {
+        let Ok(to_name) = usernames.get(challenge.to) else {
+            return false;
+        };
+        if challenge.expires > tick {
+            return clients.contains(challenge.from);
+        }
+        if let Ok(mut client) = clients.get_mut(challenge.from) {
+            client.send_chat_message(
+                "Your duel request to ".color(Color::GRAY)
+                    + to_name.0.clone().color(Color::GOLD)
+                    + " expired.".color(Color::GRAY),
+            );
+        }
+        false
+    });
+}
+
+fn send(clients: &mut Query<&mut Client>, entity: Entity, text: Text) {
+    if let Ok(mut client) = clients.get_mut(entity) {
+        client.send_chat_message(text);
+    }
+}
+
+/// Looks an enabled map up by its name, ignoring case and spaces.
+fn find_map(worlds: &[WorldValue], name: &str) -> Option<usize> {
+    let name = name.replace(' ', "");
+    voting::enabled_maps(worlds).find(|map| {
+        worlds[*map]
+            .display_name()
+            .replace(' ', "")
+            .eq_ignore_ascii_case(&name)
+    })
+}
@@ -3,25 +3,84 @@
 
 use super::*;
 use crate::config::DataPath;
-use chunkedge::anvil::AnvilLevel;
+use crate::world::ChangedBlocks;
+use chunkedge::anvil::parsing::DimensionFolder;
 use chunkedge::layer::UpdateLayersPreClientSet;
+use chunkedge::layer::chunk::UnloadedChunk;
 use chunkedge::prelude::*;
-
-#[derive(Bundle)]
-pub struct Game {
-    pub map_index: MapIndex,
-    pub layer: EntityLayerId,
-    pub clients: Entities,
-    pub game_start: GameTime,
-    pub game_stage: GameStage,
-    pub data: GameData,
-}
+use std::path::Path;
 
 #[derive(Resource)]
 pub struct MapGlobals {
     pub queue_layer: Entity,
 }
 
+/// A map that is read from disk once and then copied into a new layer for every game
+pub struct MapTemplate {
+    chunks: Vec<(ChunkPos, UnloadedChunk)>,
+}
+
+impl MapTemplate {
+    /// Reads every chunk of `world` from its region files. A map with a chunk that can't be read
+    /// fails to load as a whole, instead of being played with a hole in it.
+    pub fn load(
+        world: &WorldValue,
+        data_path: &Path,
+        biomes: &BiomeRegistry,
+    ) -> Result<Self, String> {
+        let mut folder = DimensionFolder::new(data_path.join(&world.path), biomes);
+        let mut chunks = Vec::new();
+        for z in world.z_chunks[0]..=world.z_chunks[1] {
+            for x in world.x_chunks[0]..=world.x_chunks[1] {
+                let pos = ChunkPos::new(x, z);
+                match folder.get_chunk(pos) {
+                    Ok(Some(parsed)) => chunks.push((pos, parsed.chunk)),
+                    Ok(None) => {}
+                    Err(e) => {
+                        return Err(format!(
+                            "Failed to load chunk {:?} of {}: {}",
+                            pos, world.path, e
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(Self { chunks })
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Creates a new layer holding a copy of every chunk of the map.
+    pub fn instantiate(
+        &self,
+        server: &Server,
+        dimensions: &DimensionTypeRegistry,
+        biomes: &BiomeRegistry,
+    ) -> LayerBundle {
+        let mut layer = LayerBundle::new(ident!("overworld"), dimensions, biomes, server);
+        for (pos, chunk) in self.chunks.iter() {
+            layer.chunk.insert_chunk(*pos, chunk.clone());
+        }
+        layer
+    }
+}
+
+/// The template of every map, in the same order as the config's worlds
+#[derive(Resource)]
+pub struct MapTemplates(pub Vec<MapTemplate>);
+
+/// How many layers of every map are created at startup
+const PREWARMED_LAYERS: usize = 2;
+/// How many unused layers of a map are kept around after busy periods
+const MAX_POOLED_LAYERS: usize = 8;
+
+/// Unused game layers of every map. Layers are taken out when a game starts and put back once
+/// the blocks changed during the game have been rolled back.
+#[derive(Resource, Default)]
+pub struct LayerPool(pub HashMap<usize, Vec<Entity>>);
+
 pub struct MapPlugin<T: Resource + DuelsConfig> {
     pub phantom: PhantomData<T>,
 }
@@ -29,11 +88,11 @@ pub struct MapPlugin<T: Resource + DuelsConfig> {
 impl<T: Resource + DeserializeOwned + DuelsConfig + Sync + Send + 'static> Plugin for MapPlugin<T> {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup::<T>)
-            .add_systems(Update, (init_clients::<T>,))
             .add_systems(
-                PostUpdate,
-                (check_queue::<T>, end_game::<T>).before(UpdateLayersPreClientSet),
-            );
+                Update,
+                (init_clients::<T>, place_players::<T>.after(gameloop::<T>)),
+            )
+            .add_systems(PostUpdate, end_game::<T>.before(UpdateLayersPreClientSet));
     }
 }
 
@@ -45,41 +104,39 @@ pub fn setup<T: Resource + DuelsConfig>(
     config: Res<T>,
     data_path: Res<DataPath>,
 ) {
+    let templates: Vec<MapTemplate> = config
+        .worlds()
+        .iter()
+        .map(|world| {
+            MapTemplate::load(world, &data_path.0, &biomes).unwrap_or_else(|e| panic!("{}", e))
+        })
+        .collect();
+
     let layer_id = commands
-        .spawn(init_world(
-            &config.worlds()[0],
-            &server,
-            &dimensions,
-            &biomes,
-            &data_path,
-        ))
+        .spawn(templates[0].instantiate(&server, &dimensions, &biomes))
         .id();
 
+    let mut pool = LayerPool::default();
+    for (map, template) in templates.iter().enumerate().skip(1) {
+        let layers = (0..PREWARMED_LAYERS)
+            .map(|_| {
+                commands
+                    .spawn((
+                        template.instantiate(&server, &dimensions, &biomes),
+                        ChangedBlocks::default(),
+                    ))
+                    .id()
+            })
+            .collect();
+        pool.0.insert(map, layers);
+    }
+
+    commands.insert_resource(QueueLayer(layer_id));
     commands.insert_resource(MapGlobals {
         queue_layer: layer_id,
     });
-}
-
-fn init_world(
-    world: &WorldValue,
-    server: &Res<Server>,
-    dimensions: &Res<DimensionTypeRegistry>,
-    biomes: &Res<BiomeRegistry>,
-    data_path: &Res<DataPath>,
-) -> (LayerBundle, AnvilLevel) {
-    let layer = LayerBundle::new(ident!("overworld"), dimensions, biomes, server);
-    let mut level = AnvilLevel::new(data_path.0.join(world.path.clone()), biomes);
-
-    for z in world.z_chunks[0]..=world.z_chunks[1] {
-        for x in world.x_chunks[0]..=world.x_chunks[1] {
-            let pos = ChunkPos::new(x, z);
-
-            level.ignored_chunks.insert(pos);
-            level.force_chunk_load(pos);
-        }
-    }
-
-    (layer, level)
+    commands.insert_resource(MapTemplates(templates));
+    commands.insert_resource(pool);
 }
 
 pub fn init_clients<T: Resource + DuelsConfig>(
@@ -130,7 +187,7 @@ pub fn init_clients<T: Resource + DuelsConfig>(
     }
 }
 
-pub fn check_queue<T: Resource + DuelsConfig>(
+pub fn place_players<T: Resource + DuelsConfig>(
     mut clients: Query<(
         &mut Client,
         &mut PlayerGameState,
@@ -141,107 +198,73 @@ pub fn check_queue<T: Resource + DuelsConfig>(
         &mut Look,
         &mut HeadYaw,
     )>,
-    mut start_game_ev: MessageWriter<StartGameMessage>,
-    config: Res<T>,
-    globals: Res<MapGlobals>,
+    games: Query<(&MapIndex, &EntityLayerId, &Entities, &GameStage), Without<Client>>,
+    mut stage_exit: MessageReader<StageExitMessage>,
+    mut commands: Commands,
     server: Res<Server>,
     dimensions: Res<DimensionTypeRegistry>,
     biomes: Res<BiomeRegistry>,
-    data_path: Res<DataPath>,
-    mut commands: Commands,
-    mut server_globals: ResMut<ServerGlobals>,
-) {
-    if server_globals.queue.len() < 2 {
-        return;
-    }
-    fastrand::shuffle(&mut server_globals.queue);
-    while server_globals.queue.len() > 1 {
-        start_game(
-            server_globals.queue.drain(..2).collect(),
-            &mut clients,
-            &mut start_game_ev,
-            &mut commands,
-            &server,
-            &dimensions,
-            &biomes,
-            &globals,
-            &config,
-            &data_path,
-        );
-    }
-}
-
-fn start_game<T: Resource + DuelsConfig>(
-    entities: Vec<Entity>,
-    clients: &mut Query<(
-        &mut Client,
-        &mut PlayerGameState,
-        &mut EntityLayerId,
-        &mut VisibleChunkLayer,
-        &mut VisibleEntityLayers,
-        &mut Position,
-        &mut Look,
-        &mut HeadYaw,
-    )>,
-    start_game_ev: &mut MessageWriter<StartGameMessage>,
-    commands: &mut Commands,
-    server: &Res<Server>,
-    dimensions: &Res<DimensionTypeRegistry>,
-    biomes: &Res<BiomeRegistry>,
-    globals: &Res<MapGlobals>,
-    config: &Res<T>,
-    data_path: &Res<DataPath>,
+    templates: Res<MapTemplates>,
+    mut pool: ResMut<LayerPool>,
+    globals: Res<MapGlobals>,
+    config: Res<T>,
 ) {
-    let map_idx = fastrand::usize(1..config.worlds().len());
-    let world = &config.worlds()[map_idx];
-    let layer = commands
-        .spawn(init_world(world, server, dimensions, biomes, data_path))
-        .id();
-
-    let game_id = commands
-        .spawn(Game {
-            map_index: MapIndex(map_idx),
-            layer: EntityLayerId(layer),
-            clients: Entities(entities.clone()),
-            game_start: GameTime(SystemTime::now()),
-            game_stage: GameStage(0),
-            data: GameData(HashMap::new()),
-        })
-        .id();
-
-    for (i, entity) in entities.iter().enumerate() {
-        let Ok((
-            mut client,
-            mut gamestate,
-            mut layer_id,
-            mut visible_chunk_layer,
-            mut visible_entity_layers,
-            mut pos,
-            mut look,
-            mut headyaw,
-        )) = clients.get_mut(*entity)
-        else {
+    for event in stage_exit.read() {
+        if event.stage != GameStage::Waiting {
+            continue;
+        }
+        let Ok((map, game_layer, entities, stage)) = games.get(event.game_id) else {
             continue;
         };
+        if *stage == GameStage::Ended || map.0 == 0 {
+            continue;
+        }
+        let world = &config.worlds()[map.0];
+        let chunklayer = pool
+            .0
+            .get_mut(&map.0)
+            .and_then(|layers| layers.pop())
+            .unwrap_or_else(|| {
+                commands
+                    .spawn((
+                        templates.0[map.0].instantiate(&server, &dimensions, &biomes),
+                        ChangedBlocks::default(),
+                    ))
+                    .id()
+            });
+        commands.entity(event.game_id).insert(MapLayer(chunklayer));
 
-        layer_id.0 = layer;
-        visible_chunk_layer.0 = layer;
-        visible_entity_layers.0.remove(&globals.queue_layer);
-        visible_entity_layers.0.insert(layer);
+        for entity in entities.0.iter() {
+            let Ok((
+                mut client,
+                mut gamestate,
+                mut layer_id,
+                mut visible_chunk_layer,
+                mut visible_entity_layers,
+                mut pos,
+                mut look,
+                mut headyaw,
+            )) = clients.get_mut(*entity)
+            else {
+                continue;
+            };
 
-        gamestate.game_id = Some(game_id);
-        gamestate.team = i as u8 % 2;
+            layer_id.0 = game_layer.0;
+            visible_chunk_layer.0 = chunklayer;
+            visible_entity_layers.0.remove(&globals.queue_layer);
+            visible_entity_layers.0.insert(game_layer.0);
 
-        let spawn = &world.spawns[gamestate.team as usize % 2];
-        pos.set(spawn.pos);
-        look.yaw = spawn.rot[0];
-        look.pitch = spawn.rot[1];
-        headyaw.0 = spawn.rot[0];
+            gamestate.last_map = Some(map.0);
 
-        client.send_chat_message("Game started!");
-    }
+            let spawn = world.player_spawn(gamestate.team, gamestate.team_slot);
+            pos.set(spawn.pos);
+            look.yaw = spawn.rot[0];
+            look.pitch = spawn.rot[1];
+            headyaw.0 = spawn.rot[0];
 
-    start_game_ev.write(StartGameMessage(game_id));
+            client.send_chat_message("Game started!");
+        }
+    }
 }
 
 pub fn end_game<T: Resource + DuelsConfig>(
@@ -253,16 +276,23 @@ pub fn end_game<T: Resource + DuelsConfig>(
         &mut VisibleEntityLayers,
         &mut Position,
         &mut Health,
+        &mut GameMode,
     )>,
-    games: Query<(&EntityLayerId, &Entities), Without<PlayerGameState>>,
+    games: Query<
+        (&EntityLayerId, &Entities, &MapIndex, Option<&MapLayer>),
+        Without<PlayerGameState>,
+    >,
+    mut layers: Query<(&mut ChunkLayer, &mut ChangedBlocks)>,
     mut end_game: MessageReader<EndGameMessage>,
     mut commands: Commands,
+    mut pool: ResMut<LayerPool>,
     mut server_globals: ResMut<ServerGlobals>,
     globals: Res<MapGlobals>,
+    settings: Res<GameSettings>,
     config: Res<T>,
 ) {
     for event in end_game.read() {
-        let Ok((game_layer, entities)) = games.get(event.game_id) else {
+        let Ok((game_layer, entities, map, map_layer)) = games.get(event.game_id) else {
             continue;
         };
         for entity in entities.0.iter() {
@@ -274,30 +304,48 @@ pub fn end_game<T: Resource + DuelsConfig>(
                 mut visible_entity_layers,
                 mut pos,
                 mut health,
+                mut game_mode,
             )) = clients.get_mut(*entity)
             else {
                 continue;
             };
             layer_id.0 = globals.queue_layer;
             visible_chunk_layer.0 = globals.queue_layer;
-            visible_entity_layers.0.remove(&layer_id);
+            visible_entity_layers.0.remove(&game_layer.0);
             visible_entity_layers.0.insert(globals.queue_layer);
             pos.set(config.worlds()[0].spawns[0].pos);
             health.0 = 20.0;
+            *game_mode = settings.default_gamemode;
 
-            if gamestate.team == event.loser {
-                client.send_chat_message("You lost!");
-            } else {
-                client.send_chat_message("You won!");
-                gamestate.wins += 1;
+            match event.result {
+                GameResult::Winner(team) if team == gamestate.team => {
+                    client.send_chat_message("You won!");
+                    gamestate.wins += 1;
+                }
+                GameResult::Winner(_) => client.send_chat_message("You lost!"),
+                GameResult::Draw => client.send_chat_message("Draw!"),
             }
 
             gamestate.game_id = None;
             gamestate.team = 0;
+            gamestate.team_slot = 0;
+            gamestate.eliminated = false;
 
             server_globals.queue.push(*entity);
         }
 
+        // Everyone has left the map by now, so it can be reset for the next game
+        if let Some(map_layer) = map_layer {
+            let pooled = pool.0.entry(map.0).or_default();
+            if let Ok((mut layer, mut changes)) = layers.get_mut(map_layer.0)
+                && pooled.len() < MAX_POOLED_LAYERS
+            {
+                changes.rollback(&mut layer);
+                pooled.push(map_layer.0);
+            } else {
+                commands.entity(map_layer.0).despawn();
+            }
+        }
         commands.entity(game_layer.0).despawn();
         commands.entity(event.game_id).despawn();
     }
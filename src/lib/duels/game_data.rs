@@ -0,0 +1,31 @@
+use super::*;
+
+/// Attaches a mode's own game state `D` to every game when it starts. The state is a regular
+/// component on the game entity, so modes can query it next to `Entities` and use `Ref<D>` or
+/// `Changed<D>` to only react to changes, e.g. for scoreboards.
+pub struct GameDataPlugin<D: Component + Default> {
+    pub phantom: PhantomData<D>,
+}
+
+impl<D: Component + Default> Default for GameDataPlugin<D> {
+    fn default() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<D: Component + Default> Plugin for GameDataPlugin<D> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, init_game_data::<D>.after(start_game));
+    }
+}
+
+fn init_game_data<D: Component + Default>(
+    mut start_game: MessageReader<StartGameMessage>,
+    mut commands: Commands,
+) {
+    for StartGameMessage(game_id) in start_game.read() {
+        commands.entity(*game_id).try_insert(D::default());
+    }
+}
@@ -0,0 +1,332 @@
+use super::*;
+use crate::color::ArmorColors;
+use chunkedge::item::ItemComponent;
+use chunkedge::protocol::VarInt;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Vanilla's enchantment registry, whose ids are the alphabetical order of the enchantments
+const ENCHANTMENTS: [&str; 42] = [
+    "aqua_affinity",
+    "bane_of_arthropods",
+    "binding_curse",
+    "blast_protection",
+    "breach",
+    "channeling",
+    "density",
+    "depth_strider",
+    "efficiency",
+    "feather_falling",
+    "fire_aspect",
+    "fire_protection",
+    "flame",
+    "fortune",
+    "frost_walker",
+    "impaling",
+    "infinity",
+    "knockback",
+    "looting",
+    "loyalty",
+    "luck_of_the_sea",
+    "lure",
+    "mending",
+    "multishot",
+    "piercing",
+    "power",
+    "projectile_protection",
+    "protection",
+    "punch",
+    "quick_charge",
+    "respiration",
+    "riptide",
+    "sharpness",
+    "silk_touch",
+    "smite",
+    "soul_speed",
+    "sweeping_edge",
+    "swift_sneak",
+    "thorns",
+    "unbreaking",
+    "vanishing_curse",
+    "wind_burst",
+];
+
+/// Prefix of item names that are replaced with the item of the player's team colour, e.g.
+/// `team_terracotta` becomes `blue_terracotta` for the blue team
+const TEAM_ITEM_PREFIX: &str = "team_";
+/// Number of slots a kit can use, the hotbar and the rest of the main inventory
+pub const KIT_SLOTS: u16 = 36;
+
+/// Sent by modes to hand a player their kit again, e.g. after respawning. Kits are already handed
+/// out at the start of every round.
+#[derive(Message)]
+pub struct GiveKitMessage(pub Entity);
+
+/// Where a player moved the kit's items to, keyed by the slot the kit puts them in. Items the
+/// player didn't move are left out.
+#[derive(Component, Default, Clone, Serialize, Deserialize)]
+pub struct KitLayout(pub HashMap<u16, u16>);
+
+/// The items every player gets in a game, loaded from the mode's `config.json`
+#[derive(Deserialize, Clone, Default)]
+pub struct Kit {
+    #[serde(default)]
+    pub armor: KitArmor,
+    #[serde(default)]
+    pub items: Vec<KitItem>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct KitArmor {
+    pub helmet: Option<KitStack>,
+    pub chestplate: Option<KitStack>,
+    pub leggings: Option<KitStack>,
+    pub boots: Option<KitStack>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct KitItem {
+    /// 0-8 are the hotbar and 9-35 the rest of the inventory, like vanilla's `container.<slot>`
+    pub slot: u16,
+    #[serde(flatten)]
+    pub stack: KitStack,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct KitStack {
+    pub item: KitItemKind,
+    #[serde(default = "default_count")]
+    pub count: i8,
+    /// Enchantment names mapped to their level, e.g. `{"efficiency": 2}`
+    #[serde(default)]
+    pub enchantments: HashMap<Enchantment, i32>,
+    /// Colour of leather armor, either an RGB value or `"team"`
+    #[serde(default)]
+    pub dye: Option<KitDye>,
+}
+
+fn default_count() -> i8 {
+    1
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "String")]
+pub enum KitItemKind {
+    Item(ItemKind),
+    /// An item that comes in every colour, stored as its white variant
+    Team(ItemKind),
+}
+
+impl TryFrom<String> for KitItemKind {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let unknown = || format!("unknown kit item: {}", name);
+        match name.strip_prefix(TEAM_ITEM_PREFIX) {
+            Some(base) => ItemKind::from_str(&format!("white_{}", base))
+                .map(KitItemKind::Team)
+                .ok_or_else(unknown),
+            None => ItemKind::from_str(&name)
+                .map(KitItemKind::Item)
+                .ok_or_else(unknown),
+        }
+    }
+}
+
+impl KitItemKind {
+    fn for_team(&self, team: u8) -> ItemKind {
+        match self {
+            KitItemKind::Item(kind) => *kind,
+            KitItemKind::Team(white) => {
+                let base = white.to_str().trim_start_matches("white_");
+                let name = format!("{}_{}", team_name(team).to_lowercase(), base);
+                ItemKind::from_str(&name).unwrap_or(*white)
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+pub struct Enchantment(usize);
+
+impl TryFrom<String> for Enchantment {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        ENCHANTMENTS
+            .iter()
+            .position(|enchantment| *enchantment == name)
+            .map(Enchantment)
+            .ok_or_else(|| format!("unknown enchantment: {}", name))
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+pub enum KitDye {
+    Rgb(i32),
+    Team(TeamDye),
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TeamDye {
+    Team,
+}
+
+/// Leather armor colour of every team, matching `team_color`.
+pub fn team_armor_color(team: u8) -> i32 {
+    match team {
+        0 => ArmorColors::Blue as i32,
+        1 => ArmorColors::Red as i32,
+        2 => ArmorColors::Green as i32,
+        3 => ArmorColors::Yellow as i32,
+        _ => ArmorColors::Gray as i32,
+    }
+}
+
+impl KitStack {
+    pub fn to_stack(&self, team: u8) -> ItemStack {
+        let mut components = Vec::new();
+        if !self.enchantments.is_empty() {
+            components.push(ItemComponent::Enchantments(
+                self.enchantments
+                    .iter()
+                    .map(|(enchantment, level)| {
+                        (RegistryId::new(enchantment.0 as _), VarInt(*level))
+                    })
+                    .collect(),
+            ));
+        }
+        if let Some(dye) = self.dye {
+            components.push(ItemComponent::DyedColor {
+                color: match dye {
+                    KitDye::Rgb(color) => color,
+                    KitDye::Team(_) => team_armor_color(team),
+                },
+            });
+        }
+        ItemStack::new(self.item.for_team(team), self.count).with_components(components)
+    }
+}
+
+impl Kit {
+    /// Puts the kit into `inv` following the player's layout, leaving slots the kit doesn't use
+    /// untouched.
+    pub fn apply(&self, inv: &mut Inventory, team: u8, layout: Option<&KitLayout>) {
+        let armor = [
+            (5, &self.armor.helmet),
+            (6, &self.armor.chestplate),
+            (7, &self.armor.leggings),
+            (8, &self.armor.boots),
+        ];
+        for (slot, stack) in armor {
+            if let Some(stack) = stack {
+                inv.set_slot(slot, stack.to_stack(team));
+            }
+        }
+        for (item, slot) in self.items.iter().zip(self.slots(layout)) {
+            inv.set_slot(inventory_slot(slot), item.stack.to_stack(team));
+        }
+    }
+
+    /// The slot of every item once the layout is applied. Items whose moved slot is taken, e.g.
+    /// because the kit changed since the layout was saved, go back to their own slot or the first
+    /// free one.
+    pub fn slots(&self, layout: Option<&KitLayout>) -> Vec<u16> {
+        let mut used = HashSet::new();
+        let moved: Vec<Option<u16>> = self
+            .items
+            .iter()
+            .map(|item| {
+                layout
+                    .and_then(|layout| layout.0.get(&item.slot))
+                    .copied()
+                    .filter(|slot| *slot < KIT_SLOTS && used.insert(*slot))
+            })
+            .collect();
+        self.items
+            .iter()
+            .zip(moved)
+            .map(|(item, slot)| {
+                slot.unwrap_or_else(|| {
+                    let slot = if used.contains(&item.slot) {
+                        (0..KIT_SLOTS)
+                            .find(|slot| !used.contains(slot))
+                            .unwrap_or(item.slot)
+                    } else {
+                        item.slot
+                    };
+                    used.insert(slot);
+                    slot
+                })
+            })
+            .collect()
+    }
+
+    /// Reads back where the player moved every item of the kit to. Identical items are
+    /// interchangeable, so each item is matched with the first slot holding the same item.
+    pub fn read_layout(&self, inv: &Inventory, team: u8) -> KitLayout {
+        let mut claimed = HashSet::new();
+        let mut layout = KitLayout::default();
+        for item in self.items.iter() {
+            let kind = item.stack.item.for_team(team);
+            let found = (0..KIT_SLOTS).find(|slot| {
+                !claimed.contains(slot) && inv.slot(inventory_slot(*slot)).item == kind
+            });
+            if let Some(slot) = found {
+                claimed.insert(slot);
+                if slot != item.slot {
+                    layout.0.insert(item.slot, slot);
+                }
+            }
+        }
+        layout
+    }
+}
+
+/// Converts a kit slot to the player's inventory slot, where the hotbar comes last.
+pub fn inventory_slot(slot: u16) -> u16 {
+    if slot < 9 { slot + 36 } else { slot }
+}
+
+pub struct KitPlugin<T: DuelsConfig> {
+    pub phantom: PhantomData<T>,
+}
+
+impl<T: Resource + DuelsConfig> Plugin for KitPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_message::<GiveKitMessage>()
+            .add_plugins(kit_editor::KitEditorPlugin::<T> {
+                phantom: PhantomData,
+            })
+            .add_systems(Update, give_kits::<T>.after(rounds::start_round));
+    }
+}
+
+pub fn give_kits<T: Resource + DuelsConfig>(
+    mut clients: Query<(&mut Inventory, &PlayerGameState, Option<&KitLayout>)>,
+    games: Query<&Entities>,
+    mut round_start: MessageReader<RoundStartMessage>,
+    mut give_kit: MessageReader<GiveKitMessage>,
+    config: Res<T>,
+) {
+    let Some(kit) = config.kit() else {
+        return;
+    };
+    let round_players = round_start
+        .read()
+        .filter_map(|message| games.get(message.game_id).ok())
+        .flat_map(|entities| entities.0.clone());
+    let players: Vec<Entity> = round_players
+        .chain(give_kit.read().map(|GiveKitMessage(entity)| *entity))
+        .collect();
+    for entity in players {
+        if let Ok((mut inv, gamestate, layout)) = clients.get_mut(entity)
+            && gamestate.game_id.is_some()
+        {
+            kit.apply(&mut inv, gamestate.team, layout);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+#![allow(clippy::type_complexity)]
+#![allow(clippy::too_many_arguments)]
+
+use super::*;
+use super::kit::{KIT_SLOTS, KitLayout, inventory_slot};
+use super::matchmaking::{QueueHold, player_id};
+use super::spectate::Spectating;
+use crate::db::models::MiniGameInventory;
+use crate::db::schema::minigame_inventories;
+use crate::db::{Database, DbResults, ensure_player};
+use crate::scopes::ScopePlugin;
+use chunkedge::{
+    command::{AddCommand, handler::CommandResultMessage},
+    command_macros::Command,
+    event_loop::PacketMessage,
+    inventory::HeldItem,
+    item::ItemComponent,
+    protocol::{IntoTextComponent, packets::play::UseItemC2s},
+};
+use diesel::prelude::*;
+
+/// Hotbar slot of the item that opens the kit editor in the waiting area
+const EDITOR_ITEM_SLOT: u16 = 36;
+
+#[derive(Command, Debug, Clone)]
+#[paths("kit")]
+#[scopes("minibit.commands.all.kit")]
+enum KitCommand {
+    #[paths("edit")]
+    Edit,
+    #[paths("save")]
+    Save,
+    #[paths("reset")]
+    Reset,
+    #[paths("cancel")]
+    Cancel,
+}
+
+/// Kit layouts that were read from the database, by the player they belong to
+type LoadedLayouts = DbResults<(Entity, KitLayout)>;
+
+/// Marks a player that is rearranging their kit in the waiting area
+#[derive(Component)]
+pub struct KitEditor;
+
+#[derive(Message)]
+struct KitEditMessage {
+    player: Entity,
+    action: KitCommand,
+}
+
+pub struct KitEditorPlugin<T: Resource + DuelsConfig> {
+    pub phantom: PhantomData<T>,
+}
+
+impl<T: Resource + DuelsConfig> Plugin for KitEditorPlugin<T> {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<ScopePlugin>() {
+            app.add_plugins(ScopePlugin);
+        }
+        app.add_message::<KitEditMessage>()
+            .init_resource::<LoadedLayouts>()
+            .add_command::<KitCommand>()
+            .add_systems(EventLoopUpdate, item_interactions)
+            .add_systems(
+                Update,
+                (
+                    load_layout,
+                    insert_loaded_layouts,
+                    handle_kit_command,
+                    edit_kit::<T>.after(handle_kit_command),
+                    cancel_editing
+                        .after(start_game)
+                        .before(voting::open_vote::<T>),
+                    give_editor_item::<T>.after(cancel_editing),
+                ),
+            );
+    }
+}
+
+fn load_layout(
+    clients: Query<(Entity, &UniqueId), Added<Client>>,
+    db: Res<Database>,
+    loaded: Res<LoadedLayouts>,
+    settings: Res<GameSettings>,
+) {
+    for (entity, unique_id) in clients.iter() {
+        let uuid = unique_id.0.as_u128();
+        let minigame = settings.minigame;
+        db.query(&loaded, move |conn| {
+            let saved = minigame_inventories::table
+                .filter(minigame_inventories::player_id.eq(player_id(uuid)))
+                .filter(minigame_inventories::minigame.eq(minigame))
+                .select(MiniGameInventory::as_select())
+                .first(conn)
+                .optional()
+                .unwrap_or_else(|e| {
+                    eprintln!("Couldn't load the kit layout of {}: {}", uuid, e);
+                    None
+                });
+            let layout = saved
+                .and_then(|saved| serde_json::from_value(saved.inventory).ok())
+                .unwrap_or_default();
+            (entity, layout)
+        });
+    }
+}
+
+fn insert_loaded_layouts(mut commands: Commands, loaded: Res<LoadedLayouts>) {
+    for (entity, layout) in loaded.drain() {
+        if let Ok(mut entity) = commands.get_entity(entity) {
+            entity.try_insert(layout);
+        }
+    }
+}
+
+fn handle_kit_command(
+    mut command_results: MessageReader<CommandResultMessage<KitCommand>>,
+    mut edits: MessageWriter<KitEditMessage>,
+) {
+    for message in command_results.read() {
+        edits.write(KitEditMessage {
+            player: message.executor,
+            action: message.result.clone(),
+        });
+    }
+}
+
+fn item_interactions(
+    clients: Query<(&Inventory, &HeldItem, &PlayerGameState), Without<KitEditor>>,
+    mut packets: MessageReader<PacketMessage>,
+    mut edits: MessageWriter<KitEditMessage>,
+) {
+    for packet in packets.read() {
+        if let Some(_pkt) = packet.decode::<UseItemC2s>()
+            && let Ok((inv, held_item, gamestate)) = clients.get(packet.client)
+            && gamestate.game_id.is_none()
+            && held_item.slot() == EDITOR_ITEM_SLOT
+            && inv.slot(EDITOR_ITEM_SLOT).item == ItemKind::Chest
+        {
+            edits.write(KitEditMessage {
+                player: packet.client,
+                action: KitCommand::Edit,
+            });
+        }
+    }
+}
+
+fn edit_kit<T: Resource + DuelsConfig>(
+    mut clients: Query<(
+        &mut Client,
+        &mut Inventory,
+        &PlayerGameState,
+        &UniqueId,
+        Option<&mut KitLayout>,
+        Has<KitEditor>,
+        Has<QueueHold>,
+        Has<Spectating>,
+    )>,
+    mut edits: MessageReader<KitEditMessage>,
+    mut commands: Commands,
+    db: Res<Database>,
+    settings: Res<GameSettings>,
+    config: Res<T>,
+) {
+    for KitEditMessage { player, action } in edits.read() {
+        let Ok((
+            mut client,
+            mut inv,
+            gamestate,
+            unique_id,
+            layout,
+            editing,
+            held,
+            spectating,
+        )) = clients.get_mut(*player)
+        else {
+            continue;
+        };
+        let Some(kit) = config.kit() else {
+            client.send_chat_message("This mode doesn't have a kit.".color(Color::RED));
+            continue;
+        };
+        let Some(mut layout) = layout else {
+            client.send_chat_message("Your kit is still loading.".color(Color::RED));
+            continue;
+        };
+
+        match action {
+            KitCommand::Edit => {
+                if editing {
+                    continue;
+                }
+                if gamestate.game_id.is_some() || held || spectating {
+                    client.send_chat_message(
+                        "You can only edit your kit while waiting for a game.".color(Color::RED),
+                    );
+                    continue;
+                }
+                clear(&mut inv);
+                kit.apply(&mut inv, gamestate.team, Some(&*layout));
+                commands.entity(*player).insert(KitEditor);
+                client.send_chat_message(
+                    "Rearrange your kit, then ".color(Color::GRAY)
+                        + "[Save]"
+                            .color(Color::GREEN)
+                            .bold()
+                            .on_click_run_command("/kit save")
+                        + " ".into_text()
+                        + "[Reset]"
+                            .color(Color::GOLD)
+                            .bold()
+                            .on_click_run_command("/kit reset")
+                        + " ".into_text()
+                        + "[Cancel]"
+                            .color(Color::RED)
+                            .bold()
+                            .on_click_run_command("/kit cancel"),
+                );
+            }
+            KitCommand::Save | KitCommand::Reset | KitCommand::Cancel => {
+                if !editing {
+                    client.send_chat_message(
+                        "You aren't editing your kit. Use /kit edit first.".color(Color::RED),
+                    );
+                    continue;
+                }
+                match action {
+                    KitCommand::Save => {
+                        *layout = kit.read_layout(&inv, gamestate.team);
+                        save_layout(&db, unique_id, settings.minigame, &layout);
+                        client.send_chat_message("Saved your kit layout.".color(Color::GREEN));
+                    }
+                    KitCommand::Reset => {
+                        *layout = KitLayout::default();
+                        delete_layout(&db, unique_id, settings.minigame);
+                        client.send_chat_message(
+                            "Reset your kit to the default layout.".color(Color::GREEN),
+                        );
+                    }
+                    _ => {
+                        client.send_chat_message("Discarded your changes.".color(Color::GRAY));
+                    }
+                }
+                clear(&mut inv);
+                commands.entity(*player).remove::<KitEditor>();
+            }
+        }
+    }
+}
+
+/// Stops players from editing their kit once they are put into a game. The changes are
+/// discarded, since the inventory is about to be replaced.
+fn cancel_editing(
+    mut clients: Query<(&mut Client, &mut Inventory), With<KitEditor>>,
+    games: Query<&Entities>,
+    mut start_game: MessageReader<StartGameMessage>,
+    mut commands: Commands,
+) {
+    for StartGameMessage(game_id) in start_game.read() {
+        let Ok(entities) = games.get(*game_id) else {
+            continue;
+        };
+        for entity in entities.0.iter() {
+            if let Ok((mut client, mut inv)) = clients.get_mut(*entity) {
+                clear(&mut inv);
+                client.send_chat_message(
+                    "Your game started, so your kit changes were discarded.".color(Color::GRAY),
+                );
+                commands.entity(*entity).remove::<KitEditor>();
+            }
+        }
+    }
+}
+
+fn give_editor_item<T: Resource + DuelsConfig>(
+    mut clients: Query<
+        (&mut Inventory, &PlayerGameState),
+        (With<Client>, Without<KitEditor>, Without<Spectating>),
+    >,
+    config: Res<T>,
+) {
+    if config.kit().is_none() {
+        return;
+    }
+    for (mut inv, gamestate) in clients.iter_mut() {
+        if gamestate.game_id.is_none() {
+            if inv.slot(EDITOR_ITEM_SLOT).is_empty() {
+                inv.set_slot(
+                    EDITOR_ITEM_SLOT,
+                    ItemStack::new(ItemKind::Chest, 1).with_components(vec![
+                        ItemComponent::ItemName("Edit kit".into_text_component()),
+                    ]),
+                );
+            }
+        } else if inv.slot(EDITOR_ITEM_SLOT).item == ItemKind::Chest {
+            inv.set_slot(EDITOR_ITEM_SLOT, ItemStack::EMPTY);
+        }
+    }
+}
+
+/// Empties every slot a kit can use, including the armor slots.
+fn clear(inv: &mut Inventory) {
+    for slot in (5..9).chain((0..KIT_SLOTS).map(inventory_slot)) {
+        inv.set_slot(slot, ItemStack::EMPTY);
+    }
+}
+
+fn save_layout(
+    db: &Database,
+    unique_id: &UniqueId,
+    minigame: &'static str,
+    layout: &KitLayout,
+) {
+    let Ok(inventory) = serde_json::to_value(layout) else {
+        return;
+    };
+    let uuid = unique_id.0.as_u128();
+    db.run(move |conn| {
+        let row = MiniGameInventory {
+            player_id: player_id(uuid),
+            minigame: minigame.to_string(),
+            inventory,
+        };
+        let saved = ensure_player(conn, &row.player_id).and_then(|_| {
+            diesel::insert_into(minigame_inventories::table)
+                .values(&row)
+                .on_conflict((
+                    minigame_inventories::player_id,
+                    minigame_inventories::minigame,
+                ))
+                .do_update()
+                .set(minigame_inventories::inventory.eq(&row.inventory))
+                .execute(conn)
+        });
+        if let Err(e) = saved {
+            eprintln!("Couldn't save the kit layout of {}: {}", uuid, e);
+        }
+    });
+}
+
+fn delete_layout(db: &Database, unique_id: &UniqueId, minigame: &'static str) {
+    let uuid = unique_id.0.as_u128();
+    db.run(move |conn| {
+        let deleted = diesel::delete(
+            minigame_inventories::table
+                .filter(minigame_inventories::player_id.eq(player_id(uuid)))
+                .filter(minigame_inventories::minigame.eq(minigame)),
+        )
+        .execute(conn);
+        if let Err(e) = deleted {
+            eprintln!("Couldn't reset the kit layout of {}: {}", uuid, e);
+        }
+    });
+}
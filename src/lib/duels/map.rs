@@ -5,16 +5,6 @@ use crate::config::DataPath;
 use chunkedge::anvil::AnvilLevel;
 use chunkedge::prelude::*;
 
-#[derive(Bundle)]
-pub struct Game {
-    pub map: MapIndex,
-    pub layer: EntityLayerId,
-    pub clients: Entities,
-    pub game_start: GameTime,
-    pub game_stage: GameStage,
-    pub data: GameData,
-}
-
 #[derive(Resource)]
 pub struct MapGlobals {
     pub map_layers: Vec<Entity>,
@@ -29,9 +19,9 @@ impl<T: Resource + DeserializeOwned + DuelsConfig + Sync + Send + 'static> Plugi
         app.add_systems(Startup, setup::<T>)
             .add_systems(
                 Update,
-                (init_clients::<T>, start_game::<T>.after(init_clients::<T>)),
+                (init_clients::<T>, place_players::<T>.after(gameloop::<T>)),
             )
-            .add_systems(PostUpdate, (check_queue, end_game::<T>));
+            .add_systems(PostUpdate, end_game::<T>);
     }
 }
 
@@ -60,6 +50,7 @@ pub fn setup<T: Resource + DuelsConfig>(
         layers.push(commands.spawn((layer, level)).id());
     }
 
+    commands.insert_resource(QueueLayer(layers[0]));
     commands.insert_resource(MapGlobals { map_layers: layers });
 }
 
@@ -111,35 +102,7 @@ pub fn init_clients<T: Resource + DuelsConfig>(
     }
 }
 
-pub fn check_queue(
-    mut start_game: MessageWriter<StartGameMessage>,
-    server: Res<Server>,
-    mut commands: Commands,
-    mut globals: ResMut<ServerGlobals>,
-) {
-    if globals.queue.len() < 2 {
-        return;
-    }
-    fastrand::shuffle(&mut globals.queue);
-    while globals.queue.len() > 1 {
-        let entitylayer = commands.spawn(EntityLayer::new(&server)).id();
-
-        let game_id = commands
-            .spawn(Game {
-                map: MapIndex(0),
-                layer: EntityLayerId(entitylayer),
-                clients: Entities(globals.queue.drain(..2).collect()),
-                game_start: GameTime(SystemTime::now()),
-                game_stage: GameStage(0),
-                data: GameData(HashMap::new()),
-            })
-            .id();
-
-        start_game.write(StartGameMessage(game_id));
-    }
-}
-
-pub fn start_game<T: Resource + DuelsConfig>(
+pub fn place_players<T: Resource + DuelsConfig>(
     mut clients: Query<(
         &mut Client,
         &mut PlayerGameState,
@@ -150,55 +113,54 @@ pub fn start_game<T: Resource + DuelsConfig>(
         &mut Look,
         &mut HeadYaw,
     )>,
-    mut games: Query<(&mut MapIndex, &EntityLayerId, &Entities), Without<Client>>,
-    chunklayers: Query<Entity, With<ChunkLayer>>,
-    entitylayers: Query<Entity, With<EntityLayer>>,
-    mut start_game: MessageReader<StartGameMessage>,
+    games: Query<(&MapIndex, &EntityLayerId, &Entities, &GameStage), Without<Client>>,
+    mut stage_exit: MessageReader<StageExitMessage>,
+    mut commands: Commands,
     globals: Res<MapGlobals>,
     config: Res<T>,
 ) {
-    for event in start_game.read() {
-        if let Ok((mut map, game_layer, entities)) = games.get_mut(event.0) {
-            let map_idx = fastrand::usize(1..globals.map_layers.len());
-            map.0 = map_idx;
+    for event in stage_exit.read() {
+        if event.stage != GameStage::Waiting {
+            continue;
+        }
+        let Ok((map, game_layer, entities, stage)) = games.get(event.game_id) else {
+            continue;
+        };
+        if *stage == GameStage::Ended || map.0 == 0 {
+            continue;
+        }
+        let chunklayer = globals.map_layers[map.0];
+        commands.entity(event.game_id).insert(MapLayer(chunklayer));
 
-            for (i, entity) in entities.0.iter().enumerate() {
-                let Ok((
-                    mut client,
-                    mut gamestate,
-                    mut layer_id,
-                    mut visible_chunk_layer,
-                    mut visible_entity_layers,
-                    mut pos,
-                    mut look,
-                    mut headyaw,
-                )) = clients.get_mut(*entity)
-                else {
-                    continue;
-                };
-                let Ok(chunklayer) = chunklayers.get(globals.map_layers[map_idx]) else {
-                    continue;
-                };
-                let Ok(entitylayer) = entitylayers.get(game_layer.0) else {
-                    continue;
-                };
+        for entity in entities.0.iter() {
+            let Ok((
+                mut client,
+                mut gamestate,
+                mut layer_id,
+                mut visible_chunk_layer,
+                mut visible_entity_layers,
+                mut pos,
+                mut look,
+                mut headyaw,
+            )) = clients.get_mut(*entity)
+            else {
+                continue;
+            };
 
-                layer_id.0 = entitylayer;
-                visible_chunk_layer.0 = chunklayer;
-                visible_entity_layers.0.remove(&globals.map_layers[0]);
-                visible_entity_layers.0.insert(entitylayer);
+            layer_id.0 = game_layer.0;
+            visible_chunk_layer.0 = chunklayer;
+            visible_entity_layers.0.remove(&globals.map_layers[0]);
+            visible_entity_layers.0.insert(game_layer.0);
 
-                gamestate.game_id = Some(event.0);
-                gamestate.team = i as u8 % 2;
+            gamestate.last_map = Some(map.0);
 
-                let spawn = &config.worlds()[map_idx].spawns[gamestate.team as usize % 2];
-                pos.set(spawn.pos);
-                look.yaw = spawn.rot[0];
-                look.pitch = spawn.rot[1];
-                headyaw.0 = spawn.rot[0];
+            let spawn = config.worlds()[map.0].player_spawn(gamestate.team, gamestate.team_slot);
+            pos.set(spawn.pos);
+            look.yaw = spawn.rot[0];
+            look.pitch = spawn.rot[1];
+            headyaw.0 = spawn.rot[0];
 
-                client.send_chat_message("Game started!");
-            }
+            client.send_chat_message("Game started!");
         }
     }
 }
@@ -212,12 +174,14 @@ pub fn end_game<T: Resource + DuelsConfig>(
         &mut VisibleEntityLayers,
         &mut Position,
         &mut Health,
+        &mut GameMode,
     )>,
     games: Query<(&EntityLayerId, &Entities), Without<PlayerGameState>>,
     mut end_game: MessageReader<EndGameMessage>,
     mut commands: Commands,
     mut server_globals: ResMut<ServerGlobals>,
     globals: Res<MapGlobals>,
+    settings: Res<GameSettings>,
     config: Res<T>,
 ) {
     for event in end_game.read() {
@@ -233,26 +197,32 @@ pub fn end_game<T: Resource + DuelsConfig>(
                 mut visible_entity_layers,
                 mut pos,
                 mut health,
+                mut game_mode,
             )) = clients.get_mut(*entity)
             else {
                 continue;
             };
             layer_id.0 = globals.map_layers[0];
             visible_chunk_layer.0 = globals.map_layers[0];
-            visible_entity_layers.0.remove(&layer_id);
+            visible_entity_layers.0.remove(&game_layer.0);
             visible_entity_layers.0.insert(globals.map_layers[0]);
             pos.set(config.worlds()[0].spawns[0].pos);
             health.0 = 20.0;
-
-            if gamestate.team == event.loser {
-                client.send_chat_message("You lost!");
-            } else {
-                client.send_chat_message("You won!");
-                gamestate.wins += 1;
+            *game_mode = settings.default_gamemode;
+
+            match event.result {
+                GameResult::Winner(team) if team == gamestate.team => {
+                    client.send_chat_message("You won!");
+                    gamestate.wins += 1;
+                }
+                GameResult::Winner(_) => client.send_chat_message("You lost!"),
+                GameResult::Draw => client.send_chat_message("Draw!"),
             }
 
             gamestate.game_id = None;
             gamestate.team = 0;
+            gamestate.team_slot = 0;
+            gamestate.eliminated = false;
 
             server_globals.queue.push(*entity);
         }
@@ -0,0 +1,407 @@
+#![allow(clippy::type_complexity)]
+#![allow(clippy::too_many_arguments)]
+
+use super::*;
+use crate::db::{Database, DbResults, ensure_player};
+use crate::db::schema::minigame_stats;
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use diesel::prelude::*;
+use std::ops::Range;
+
+pub const DEFAULT_RATING: f64 = 1000.0;
+const RATING_KEY: &str = "rating";
+const K_FACTOR: f64 = 32.0;
+/// Rating difference that is always accepted when matching players
+const BASE_RATING_GAP: f64 = 100.0;
+/// How much the accepted rating difference grows for every second a player waits in the queue
+const RATING_GAP_PER_SEC: f64 = 10.0;
+
+/// Elo rating of a player in the current minigame
+#[derive(Component, Clone, Copy)]
+pub struct Rating(pub f64);
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self(DEFAULT_RATING)
+    }
+}
+
+/// Marks a game that doesn't affect the ratings of its players, like a duel between friends
+#[derive(Component)]
+pub struct Unranked;
+
+/// Keeps a queued player from being matched, e.g. while they decide on a rematch
+#[derive(Component)]
+pub struct QueueHold;
+
+/// Tracks when every player in the queue joined it
+#[derive(Resource, Default)]
+pub struct Matchmaker {
+    since: HashMap<Entity, i64>,
+}
+
+impl Matchmaker {
+    /// How many ticks a player has been waiting in the queue for.
+    pub fn waited(&self, entity: Entity, tick: i64) -> Option<i64> {
+        self.since.get(&entity).map(|since| tick - since)
+    }
+}
+
+struct Candidate {
+    entity: Entity,
+    rating: f64,
+    waited: i64,
+}
+
+pub fn check_queue(
+    ratings: Query<&Rating>,
+    held: Query<(), With<QueueHold>>,
+    mut start_game: MessageWriter<StartGameMessage>,
+    server: Res<Server>,
+    mut commands: Commands,
+    mut globals: ResMut<ServerGlobals>,
+    mut matchmaker: ResMut<Matchmaker>,
+    settings: Res<GameSettings>,
+) {
+    let tick = server.current_tick();
+    let queue = &mut globals.queue;
+    matchmaker.since.retain(|entity, _| queue.contains(entity));
+    for entity in queue.iter() {
+        matchmaker.since.entry(*entity).or_insert(tick);
+    }
+
+    let players_per_game = settings.players_per_game();
+    if queue.len() < players_per_game {
+        return;
+    }
+
+    let mut candidates: Vec<Candidate> = queue
+        .iter()
+        .filter(|entity| !held.contains(**entity))
+        .filter_map(|entity| {
+            // Players whose rating is still loading have to wait for it
+            let rating = ratings.get(*entity).ok()?;
+            Some(Candidate {
+                entity: *entity,
+                rating: rating.0,
+                waited: tick - matchmaker.since[entity],
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.rating.total_cmp(&b.rating));
+
+    while let Some(range) = find_match(&candidates, players_per_game) {
+        let players: Vec<Candidate> = candidates.drain(range).collect();
+        queue.retain(|entity| !players.iter().any(|player| player.entity == *entity));
+
+        let players = balance_teams(players, settings.teams);
+        let game_id = spawn_game(&mut commands, &server, &settings, players, None);
+        start_game.write(StartGameMessage(game_id));
+    }
+}
+
+/// Finds the players with the closest ratings around each player, starting with whoever waited
+/// the longest. A group is only accepted if its rating spread fits within that player's gap.
+fn find_match(candidates: &[Candidate], size: usize) -> Option<Range<usize>> {
+    if candidates.len() < size {
+        return None;
+    }
+    let spread = |start: usize| candidates[start + size - 1].rating - candidates[start].rating;
+
+    let mut anchors: Vec<usize> = (0..candidates.len()).collect();
+    anchors.sort_by_key(|i| std::cmp::Reverse(candidates[*i].waited));
+
+    for anchor in anchors {
+        let gap = BASE_RATING_GAP + RATING_GAP_PER_SEC * (candidates[anchor].waited / 20) as f64;
+        let first = (anchor + 1).saturating_sub(size);
+        let last = anchor.min(candidates.len() - size);
+        let best = (first..=last).min_by(|a, b| spread(*a).total_cmp(&spread(*b)))?;
+        if spread(best) <= gap {
+            return Some(best..best + size);
+        }
+    }
+    None
+}
+
+/// Orders a group so that the round-robin team assignment of `spawn_game` drafts the players
+/// into teams in a snake order, from the highest rating to the lowest.
+fn balance_teams(mut players: Vec<Candidate>, teams: u8) -> Vec<Entity> {
+    players.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+    let teams = teams as usize;
+    let mut ordered = vec![Entity::PLACEHOLDER; players.len()];
+    for (rank, player) in players.into_iter().enumerate() {
+        let round = rank / teams;
+        let pos = rank % teams;
+        let team = if round % 2 == 0 { pos } else { teams - 1 - pos };
+        ordered[round * teams + team] = player.entity;
+    }
+    ordered
+}
+
+/// Expected score of a team rated `rating` against a team rated `opponent`.
+fn expected_score(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+/// Ratings that were read from the database, by the player they belong to
+pub type LoadedRatings = DbResults<(Entity, f64)>;
+
+/// Looks up the rating of every player that joins. Players can't be matched until it arrives.
+/// Without a database, every player starts at the default rating, which is only kept in memory.
+pub fn load_rating(
+    clients: Query<(Entity, &UniqueId), Added<Client>>,
+    mut commands: Commands,
+    db: Res<Database>,
+    loaded: Res<LoadedRatings>,
+    settings: Res<GameSettings>,
+) {
+    for (entity, unique_id) in clients.iter() {
+        if !db.is_enabled() {
+            commands.entity(entity).insert(Rating::default());
+            continue;
+        }
+        let uuid = unique_id.0.as_u128();
+        let minigame = settings.minigame;
+        db.query(&loaded, move |conn| {
+            let rating = minigame_stats::table
+                .filter(minigame_stats::player_id.eq(player_id(uuid)))
+                .filter(minigame_stats::minigame.eq(minigame))
+                .filter(minigame_stats::stat_key.eq(RATING_KEY))
+                .select(minigame_stats::stat_value)
+                .first::<BigDecimal>(conn)
+                .optional()
+                .unwrap_or_else(|e| {
+                    eprintln!("Couldn't load the rating of {}: {}", uuid, e);
+                    None
+                });
+            (
+                entity,
+                rating
+                    .and_then(|value| value.to_f64())
+                    .unwrap_or(DEFAULT_RATING),
+            )
+        });
+    }
+}
+
+pub fn insert_loaded_ratings(mut commands: Commands, loaded: Res<LoadedRatings>) {
+    for (entity, rating) in loaded.drain() {
+        // The player might have left while their rating was loading
+        if let Ok(mut entity) = commands.get_entity(entity) {
+            entity.try_insert(Rating(rating));
+        }
+    }
+}
+
+/// A player that left a game before it ended
+pub struct Leaver {
+    pub uuid: u128,
+    pub team: u8,
+    pub rating: f64,
+}
+
+/// Players that left the game, who are rated as having lost it
+#[derive(Component, Default)]
+pub struct Leavers(pub Vec<Leaver>);
+
+impl Leavers {
+    pub fn add(&mut self, uuid: u128, team: u8, rating: f64) {
+        self.0.retain(|leaver| leaver.uuid != uuid);
+        self.0.push(Leaver { uuid, team, rating });
+    }
+
+    /// Takes a player that rejoined back out of the leavers, returning their rating.
+    pub fn take(&mut self, uuid: u128) -> Option<f64> {
+        let index = self.0.iter().position(|leaver| leaver.uuid == uuid)?;
+        Some(self.0.remove(index).rating)
+    }
+}
+
+pub fn update_ratings(
+    mut players: Query<(&mut Client, &PlayerGameState, &mut Rating, &UniqueId)>,
+    games: Query<(&Entities, &Leavers), Without<Unranked>>,
+    mut end_game: MessageReader<EndGameMessage>,
+    db: Res<Database>,
+    settings: Res<GameSettings>,
+) {
+    for event in end_game.read() {
+        let Ok((entities, leavers)) = games.get(event.game_id) else {
+            continue;
+        };
+        let ratings = entities
+            .0
+            .iter()
+            .filter_map(|entity| players.get(*entity).ok())
+            .map(|(_, gamestate, rating, _)| (gamestate.team, rating.0))
+            .chain(leavers.0.iter().map(|leaver| (leaver.team, leaver.rating)));
+        let teams = team_averages(ratings, settings.teams);
+        if teams.len() < 2 {
+            continue;
+        }
+
+        for entity in entities.0.iter() {
+            let Ok((mut client, gamestate, mut rating, unique_id)) = players.get_mut(*entity)
+            else {
+                continue;
+            };
+            let delta = rating_delta(&teams, gamestate.team as usize, event.result, false);
+            rating.0 += delta;
+
+            let change = delta.round() as i64;
+            let change = if change >= 0 {
+                format!("+{}", change).color(Color::GREEN)
+            } else {
+                change.to_string().color(Color::RED)
+            };
+            client.send_chat_message(
+                "Rating: ".color(Color::GRAY)
+                    + (rating.0.round() as i64).to_string().color(Color::GOLD)
+                    + " (".color(Color::GRAY)
+                    + change
+                    + ")".color(Color::GRAY),
+            );
+
+            save_rating(&db, unique_id.0.as_u128(), settings.minigame, rating.0);
+        }
+        for leaver in leavers.0.iter() {
+            let rating =
+                leaver.rating + rating_delta(&teams, leaver.team as usize, event.result, true);
+            save_rating(&db, leaver.uuid, settings.minigame, rating);
+        }
+    }
+}
+
+/// The average rating of every team that had players in a game, by team.
+fn team_averages(ratings: impl Iterator<Item = (u8, f64)>, teams: u8) -> Vec<(usize, f64)> {
+    let mut totals = vec![(0.0, 0); teams as usize];
+    for (team, rating) in ratings {
+        if let Some(total) = totals.get_mut(team as usize) {
+            total.0 += rating;
+            total.1 += 1;
+        }
+    }
+    totals
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, count))| *count > 0)
+        .map(|(team, (sum, count))| (team, sum / *count as f64))
+        .collect()
+}
+
+/// How much the rating of a player on `team` changes. Every team is scored against every other
+/// team, where a draw counts as half a win. Players that left count as having lost against
+/// everyone.
+fn rating_delta(teams: &[(usize, f64)], team: usize, result: GameResult, left: bool) -> f64 {
+    let avg = teams.iter().find(|(t, _)| *t == team).map_or(0.0, |(_, avg)| *avg);
+    let mut delta = 0.0;
+    for (opponent, opponent_avg) in teams.iter() {
+        if *opponent == team {
+            continue;
+        }
+        let score = match result {
+            _ if left => 0.0,
+            GameResult::Winner(winner) if winner as usize == team => 1.0,
+            GameResult::Winner(winner) if winner as usize == *opponent => 0.0,
+            GameResult::Winner(_) => continue,
+            GameResult::Draw => 0.5,
+        };
+        delta += K_FACTOR * (score - expected_score(avg, *opponent_avg));
+    }
+    delta / (teams.len() - 1) as f64
+}
+
+pub(crate) fn player_id(uuid: u128) -> BigDecimal {
+    uuid.to_string().parse().unwrap()
+}
+
+fn save_rating(db: &Database, uuid: u128, minigame: &'static str, rating: f64) {
+    let Some(value) = BigDecimal::from_f64(rating.round()) else {
+        return;
+    };
+    db.run(move |conn| {
+        let player_id = player_id(uuid);
+        let saved = ensure_player(conn, &player_id).and_then(|_| {
+            diesel::insert_into(minigame_stats::table)
+                .values((
+                    minigame_stats::player_id.eq(&player_id),
+                    minigame_stats::minigame.eq(minigame),
+                    minigame_stats::stat_key.eq(RATING_KEY),
+                    minigame_stats::stat_value.eq(&value),
+                ))
+                .on_conflict((
+                    minigame_stats::player_id,
+                    minigame_stats::minigame,
+                    minigame_stats::stat_key,
+                ))
+                .do_update()
+                .set(minigame_stats::stat_value.eq(&value))
+                .execute(conn)
+        });
+        if let Err(e) = saved {
+            eprintln!("Couldn't save the rating of {}: {}", uuid, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn even_teams_win_and_lose_half_the_k_factor() {
+        let teams = team_averages([(0, 1000.0), (1, 1000.0)].into_iter(), 2);
+        assert_eq!(rating_delta(&teams, 0, GameResult::Winner(0), false), K_FACTOR / 2.0);
+        assert_eq!(rating_delta(&teams, 1, GameResult::Winner(0), false), -K_FACTOR / 2.0);
+        assert_eq!(rating_delta(&teams, 0, GameResult::Draw, false), 0.0);
+    }
+
+    #[test]
+    fn upsets_are_worth_more() {
+        let teams = team_averages([(0, 1200.0), (1, 1000.0)].into_iter(), 2);
+        let favorite_win = rating_delta(&teams, 0, GameResult::Winner(0), false);
+        let underdog_win = rating_delta(&teams, 1, GameResult::Winner(1), false);
+        assert!(underdog_win > favorite_win);
+        // The favorite loses rating on a draw
+        assert!(rating_delta(&teams, 0, GameResult::Draw, false) < 0.0);
+    }
+
+    #[test]
+    fn teams_are_rated_by_their_average() {
+        let ratings = [(0, 1100.0), (1, 1000.0), (0, 900.0), (1, 1000.0)];
+        assert_eq!(team_averages(ratings.into_iter(), 2), vec![(0, 1000.0), (1, 1000.0)]);
+    }
+
+    #[test]
+    fn leavers_lose_even_if_their_team_won() {
+        let teams = team_averages([(0, 1000.0), (1, 1000.0)].into_iter(), 2);
+        assert_eq!(rating_delta(&teams, 0, GameResult::Winner(0), true), -K_FACTOR / 2.0);
+    }
+
+    #[test]
+    fn teams_that_neither_won_nor_lost_against_each_other_are_skipped() {
+        let teams = team_averages([(0, 1000.0), (1, 1000.0), (2, 1000.0)].into_iter(), 3);
+        // Team 1 only lost against team 0, averaged over both opponents
+        assert_eq!(rating_delta(&teams, 1, GameResult::Winner(0), false), -K_FACTOR / 4.0);
+        assert_eq!(rating_delta(&teams, 0, GameResult::Winner(0), false), K_FACTOR / 2.0);
+    }
+
+    #[test]
+    fn teams_are_drafted_in_snake_order() {
+        let mut world = World::new();
+        let entities: Vec<Entity> = (0..4).map(|_| world.spawn_empty().id()).collect();
+        let players = entities
+            .iter()
+            .enumerate()
+            .map(|(i, entity)| Candidate {
+                entity: *entity,
+                rating: 1000.0 + i as f64 * 100.0,
+                waited: 0,
+            })
+            .collect();
+        let ordered = balance_teams(players, 2);
+        // spawn_game assigns players round-robin, so team 0 gets the 1st and 3rd entity
+        assert_eq!([ordered[0], ordered[2]], [entities[3], entities[0]]);
+        assert_eq!([ordered[1], ordered[3]], [entities[2], entities[1]]);
+    }
+}
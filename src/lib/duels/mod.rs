@@ -1,8 +1,22 @@
 #![allow(dead_code)]
+#![allow(clippy::too_many_arguments)]
 
+pub mod challenge;
 pub mod copied_map;
+pub mod game_data;
+pub mod kit;
+pub mod kit_editor;
 pub mod map;
+pub mod matchmaking;
 pub mod oob;
+pub mod queue;
+pub mod reconnect;
+pub mod rematch;
+pub mod rounds;
+pub mod spectate;
+pub mod stats;
+pub mod time_limit;
+pub mod voting;
 
 use bevy_ecs::query::QueryData;
 use chunkedge::{
@@ -13,60 +27,192 @@ use chunkedge::{
 };
 use serde::{Deserialize, de::DeserializeOwned};
 use std::path::PathBuf;
-use std::{collections::HashMap, marker::PhantomData, time::SystemTime};
+use std::{collections::HashMap, marker::PhantomData};
 
+use super::combat::{CombatRules, KnockbackConfig, KnockbackPlugin};
 use super::config::{ConfigLoaderPlugin, NetworkConfig, WorldValue};
+use super::db::DatabasePlugin;
+use super::effects::StatusEffects;
+use super::food::hunger::Hunger;
+use kit::Kit;
+use matchmaking::{Leavers, Rating};
+use reconnect::{PlayerSnapshot, Reconnects};
+use rounds::{RoundEndMessage, RoundStartMessage};
+use stats::{HitMessage, MatchStats};
+use time_limit::TimeLimitOutcome;
+use voting::MapVotes;
 
+/// The map a game is played on. Index 0 is the waiting area, so a game that is still voting
+/// for its map has an index of 0.
 #[derive(Component)]
 pub struct MapIndex(pub usize);
 
+/// The chunk layer of the map a game is played on, inserted once players are moved onto the map.
 #[derive(Component)]
-pub struct Entities(pub Vec<Entity>);
+pub struct MapLayer(pub Entity);
 
 #[derive(Component)]
-pub struct GameTime(pub SystemTime);
+pub struct Entities(pub Vec<Entity>);
 
+/// Tick-based game timers. `start` is kept for the whole game while `stage_start` is reset on
+/// every stage change. `played` counts the ticks spent in the playing stage over all rounds.
 #[derive(Component)]
-pub struct GameStage(pub u8);
+pub struct GameTime {
+    pub start: i64,
+    pub stage_start: i64,
+    pub played: i64,
+}
 
-pub enum DataValue {
-    Int(i32),
-    Float(f32),
-    String(String),
+impl GameTime {
+    pub fn new(tick: i64) -> Self {
+        Self {
+            start: tick,
+            stage_start: tick,
+            played: 0,
+        }
+    }
+
+    pub fn elapsed(&self, tick: i64) -> i64 {
+        tick - self.start
+    }
+
+    pub fn stage_elapsed(&self, tick: i64) -> i64 {
+        tick - self.stage_start
+    }
 }
 
-#[derive(Component)]
-pub struct GameData(pub HashMap<usize, DataValue>);
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameStage {
+    /// The game has been created and the map is being voted on
+    Waiting,
+    /// Players are frozen at their spawns while the countdown runs
+    Countdown,
+    Playing,
+    /// A round has been decided and the next countdown is about to start
+    RoundOver,
+    Ended,
+}
+
+impl GameStage {
+    /// Whether players should be held at their spawns during this stage.
+    pub fn is_frozen(&self) -> bool {
+        matches!(
+            self,
+            GameStage::Waiting | GameStage::Countdown | GameStage::RoundOver
+        )
+    }
+}
+
+#[derive(Bundle)]
+pub struct Game {
+    pub map: MapIndex,
+    pub layer: EntityLayerId,
+    pub clients: Entities,
+    pub game_start: GameTime,
+    pub game_stage: GameStage,
+    pub rounds: rounds::Rounds,
+    pub votes: MapVotes,
+    pub leavers: Leavers,
+}
 
 #[derive(Component, Default)]
 pub struct PlayerGameState {
     pub game_id: Option<Entity>,
     pub team: u8,
+    /// Which player of their team the player is, which picks their spawn
+    pub team_slot: u8,
     pub wins: u32,
+    pub eliminated: bool,
+    /// The map of the player's previous game, which is avoided when picking the next one
+    pub last_map: Option<usize>,
 }
 
 #[derive(Component, Default)]
 pub struct CombatState {
     pub last_attacked_tick: i64,
+    /// Set when the player starts sprinting and used up by their next hit, so that sprinting
+    /// again (a sprint reset) is needed for the next hit to get the bonus
     pub has_bonus_knockback: bool,
+    pub sprinting: bool,
+    /// Tick the player last stopped sprinting at
+    pub sprint_stop_tick: i64,
     pub last_attacker: Option<Entity>,
+    /// Melee hits taken in a row from `last_attacker`
+    pub combo: u32,
+    /// Tick the player last used their sword at, while they haven't released right click
+    pub block_tick: Option<i64>,
+    /// Tick of the player's last swing, which the 1.9+ attack cooldown is counted from
+    pub last_swing_tick: i64,
+    /// Tick the player raised their shield at, if it is still raised
+    pub shield_tick: Option<i64>,
+    /// Tick until which an axe hit keeps the player's shield from blocking
+    pub shield_disabled_until: i64,
+    /// Hits of the player that were rejected since they were last logged
+    pub rejected_hits: u32,
+    /// Tick the player's rejected hits were last logged at
+    pub rejected_log_tick: i64,
 }
 
 #[derive(Message)]
 pub struct StartGameMessage(pub Entity);
 
+/// How a game or round was decided
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    Winner(u8),
+    Draw,
+}
+
+impl GameResult {
+    /// The winning team, if any.
+    pub fn winner(&self) -> Option<u8> {
+        match self {
+            GameResult::Winner(team) => Some(*team),
+            GameResult::Draw => None,
+        }
+    }
+}
+
 #[derive(Message)]
 pub struct EndGameMessage {
     pub game_id: Entity,
-    pub loser: u8,
+    pub result: GameResult,
+}
+
+/// Removes a player from the game. The round ends once all but one team has been eliminated.
+#[derive(Message)]
+pub struct EliminationMessage {
+    pub game_id: Entity,
+    pub player: Entity,
 }
 
 #[derive(Message)]
-pub struct GameStageMessage {
+pub struct StageEnterMessage {
     pub game_id: Entity,
-    pub stage: u8,
+    pub stage: GameStage,
 }
 
+#[derive(Message)]
+pub struct StageExitMessage {
+    pub game_id: Entity,
+    pub stage: GameStage,
+}
+
+/// Sent at the start of every second of the countdown.
+#[derive(Message)]
+pub struct CountdownMessage {
+    pub game_id: Entity,
+    pub seconds: u32,
+}
+
+/// Ends the current round of a playing game and runs the countdown again without ending the game.
+#[derive(Message)]
+pub struct NewRoundMessage(pub Entity);
+
+/// The layer of the waiting area, where players are while they are queued
+#[derive(Resource)]
+pub struct QueueLayer(pub Entity);
+
 #[derive(Resource)]
 pub struct ServerGlobals {
     pub queue: Vec<Entity>,
@@ -74,32 +220,177 @@ pub struct ServerGlobals {
 
 #[derive(Resource)]
 pub struct GameSettings {
+    pub minigame: &'static str,
     pub default_gamemode: GameMode,
+    pub teams: u8,
+    pub team_size: usize,
+    pub countdown_secs: u32,
+    pub round_over_secs: u32,
+    pub rounds_to_win: u32,
+    pub map_vote_secs: u32,
+    pub reconnect_grace_secs: u32,
+    pub rematch_secs: u32,
+    pub rematch_same_map: bool,
+    pub time_limit_secs: u32,
+    pub time_limit_outcome: TimeLimitOutcome,
+}
+
+impl GameSettings {
+    pub fn players_per_game(&self) -> usize {
+        self.teams as usize * self.team_size
+    }
+
+    /// Whether a game has been played for longer than the mode's time limit.
+    pub fn is_time_up(&self, time: &GameTime) -> bool {
+        self.time_limit_secs > 0 && time.played >= self.time_limit_secs as i64 * 20
+    }
+}
+
+pub fn team_name(team: u8) -> &'static str {
+    match team {
+        0 => "Blue",
+        1 => "Red",
+        2 => "Green",
+        3 => "Yellow",
+        _ => "Gray",
+    }
+}
+
+pub fn team_color(team: u8) -> Color {
+    match team {
+        0 => Color::BLUE,
+        1 => Color::RED,
+        2 => Color::GREEN,
+        3 => Color::YELLOW,
+        _ => Color::GRAY,
+    }
 }
 
 pub trait DuelsConfig {
     fn worlds(&self) -> &Vec<WorldValue>;
+
+    /// The kit handed out at the start of every round, if the mode has one
+    fn kit(&self) -> Option<&Kit> {
+        None
+    }
+
+    fn knockback(&self) -> Option<&KnockbackConfig> {
+        None
+    }
+
+    /// Overrides the rules `CombatPlugin` was added with
+    fn combat_rules(&self) -> Option<CombatRules> {
+        None
+    }
+
+    /// Overrides the number of teams `DuelsPlugin` was added with
+    fn teams(&self) -> Option<u8> {
+        None
+    }
+
+    /// Overrides the team size `DuelsPlugin` was added with, e.g. to run a mode as 2v2
+    fn team_size(&self) -> Option<usize> {
+        None
+    }
 }
 
 #[derive(Resource, Deserialize)]
 pub struct DefaultDuelsConfig {
     pub worlds: Vec<WorldValue>,
+    #[serde(default)]
+    pub kit: Option<Kit>,
+    #[serde(default)]
+    pub knockback: Option<KnockbackConfig>,
+    #[serde(default)]
+    pub combat_rules: Option<CombatRules>,
+    #[serde(default)]
+    pub teams: Option<u8>,
+    #[serde(default)]
+    pub team_size: Option<usize>,
 }
 
 impl DuelsConfig for DefaultDuelsConfig {
     fn worlds(&self) -> &Vec<WorldValue> {
         &self.worlds
     }
+
+    fn kit(&self) -> Option<&Kit> {
+        self.kit.as_ref()
+    }
+
+    fn knockback(&self) -> Option<&KnockbackConfig> {
+        self.knockback.as_ref()
+    }
+
+    fn combat_rules(&self) -> Option<CombatRules> {
+        self.combat_rules
+    }
+
+    fn teams(&self) -> Option<u8> {
+        self.teams
+    }
+
+    fn team_size(&self) -> Option<usize> {
+        self.team_size
+    }
 }
 
 pub struct DuelsPlugin<T: DeserializeOwned + DuelsConfig> {
     pub path: PathBuf,
     pub network_config: NetworkConfig,
+    /// Database that ratings and kit layouts are stored in. Without one, nothing is saved.
+    pub database_url: String,
+    /// Name the mode's stats are stored under
+    pub minigame: &'static str,
     pub default_gamemode: GameMode,
     pub copy_map: bool,
+    /// Used unless the mode's config sets its own teams
+    pub teams: u8,
+    pub team_size: usize,
+    pub countdown_secs: u32,
+    pub round_over_secs: u32,
+    pub rounds_to_win: u32,
+    /// How long players can vote for a map before the game starts. 0 disables voting.
+    pub map_vote_secs: u32,
+    /// How long a disconnected player has to rejoin their game before forfeiting. 0 forfeits
+    /// immediately.
+    pub reconnect_grace_secs: u32,
+    /// How long players can accept a rematch after a game. 0 disables rematches.
+    pub rematch_secs: u32,
+    /// Whether rematches are played on the same map instead of voting for a new one
+    pub rematch_same_map: bool,
+    /// How long a game can be played before `time_limit_outcome` decides it. Only time spent
+    /// fighting counts, not countdowns. 0 disables the time limit.
+    pub time_limit_secs: u32,
+    pub time_limit_outcome: TimeLimitOutcome,
     pub phantom: PhantomData<T>,
 }
 
+impl<T: DeserializeOwned + DuelsConfig> Default for DuelsPlugin<T> {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            network_config: NetworkConfig::default(),
+            database_url: String::new(),
+            minigame: "duels",
+            default_gamemode: GameMode::Adventure,
+            copy_map: false,
+            teams: 2,
+            team_size: 1,
+            countdown_secs: 3,
+            round_over_secs: 2,
+            rounds_to_win: 1,
+            map_vote_secs: 0,
+            reconnect_grace_secs: 30,
+            rematch_secs: 10,
+            rematch_same_map: true,
+            time_limit_secs: 0,
+            time_limit_outcome: TimeLimitOutcome::Draw,
+            phantom: PhantomData,
+        }
+    }
+}
+
 impl<T: Resource + DeserializeOwned + DuelsConfig + Sync + Send + 'static> Plugin
     for DuelsPlugin<T>
 {
@@ -108,84 +399,474 @@ impl<T: Resource + DeserializeOwned + DuelsConfig + Sync + Send + 'static> Plugi
             path: self.path.clone(),
             network_config: self.network_config.clone(),
             phantom: PhantomData,
-        })
+        });
+        let config = app.world().resource::<T>();
+        let teams = config.teams().unwrap_or(self.teams);
+        let team_size = config.team_size().unwrap_or(self.team_size);
+
+        app.add_plugins(DatabasePlugin::new(self.database_url.clone()))
         .insert_resource(GameSettings {
+            minigame: self.minigame,
             default_gamemode: self.default_gamemode,
+            teams,
+            team_size,
+            countdown_secs: self.countdown_secs,
+            round_over_secs: self.round_over_secs,
+            rounds_to_win: self.rounds_to_win,
+            map_vote_secs: self.map_vote_secs,
+            reconnect_grace_secs: self.reconnect_grace_secs,
+            rematch_secs: self.rematch_secs,
+            rematch_same_map: self.rematch_same_map,
+            time_limit_secs: self.time_limit_secs,
+            time_limit_outcome: self.time_limit_outcome,
         })
         .insert_resource(ServerGlobals { queue: Vec::new() })
+        .insert_resource(matchmaking::Matchmaker::default())
+        .init_resource::<matchmaking::LoadedRatings>()
+        .insert_resource(Reconnects::default())
+        .add_plugins(challenge::ChallengePlugin::<T> {
+            phantom: PhantomData,
+        })
+        .add_plugins(spectate::SpectatePlugin::<T> {
+            phantom: PhantomData,
+        })
+        .add_plugins(rematch::RematchPlugin)
+        .add_plugins(kit::KitPlugin::<T> {
+            phantom: PhantomData,
+        })
+        .add_plugins(KnockbackPlugin::<T> {
+            phantom: PhantomData,
+        })
+        .add_plugins(queue::QueueStatusPlugin)
         .add_message::<StartGameMessage>()
         .add_message::<EndGameMessage>()
-        .add_message::<GameStageMessage>()
+        .add_message::<EliminationMessage>()
+        .add_message::<StageEnterMessage>()
+        .add_message::<StageExitMessage>()
+        .add_message::<CountdownMessage>()
+        .add_message::<NewRoundMessage>()
+        .add_message::<RoundEndMessage>()
+        .add_message::<RoundStartMessage>()
+        .add_message::<HitMessage>()
+        // Tracked for the match summary even in modes without placing or golden apples
+        .add_message::<crate::world::BlockPlaceMessage>()
+        .add_message::<crate::food::golden_apple::GoldenAppleEatenMessage>()
+        .add_systems(Startup, (check_spawns::<T>, voting::setup::<T>))
+        .add_systems(
+            EventLoopUpdate,
+            (voting::vote_item_interactions, voting::handle_vote_click::<T>),
+        )
         .add_systems(
             Update,
             (
                 despawn_disconnected_clients,
                 start_game,
-                gamestage_change.after(gameloop::<T>),
+                end_stage,
+                voting::open_vote::<T>.after(start_game),
+                voting::select_map::<T>.before(gameloop::<T>),
+                voting::close_vote.after(gameloop::<T>),
+                stage_titles.after(gameloop::<T>),
+                rounds::start_round.after(gameloop::<T>),
                 chat_message,
+                matchmaking::load_rating,
+                matchmaking::insert_loaded_ratings,
+                matchmaking::update_ratings,
+                reconnect::expire_reconnects,
+                time_limit::check_time_limit::<T>.after(gameloop::<T>),
+                time_limit::sudden_death::<T>.after(time_limit::check_time_limit::<T>),
+                time_limit::lift_border.after(gameloop::<T>).after(end_stage),
+                stats::reset_stats,
+                stats::track_stats.after(stats::reset_stats),
+                stats::show_summary.after(stats::track_stats),
             ),
         )
-        .add_systems(PostUpdate, handle_disconnect);
+        .add_systems(
+            PreUpdate,
+            (
+                handle_elimination::<T>,
+                rounds::handle_round_end.after(handle_elimination::<T>),
+            ),
+        )
+        .add_systems(Update, reset_status.after(rounds::start_round))
+        .add_systems(PostUpdate, (handle_disconnect, matchmaking::check_queue));
 
         if self.copy_map {
             app.add_plugins(copied_map::MapPlugin::<T> {
                 phantom: PhantomData,
             })
-            .add_systems(Update, gameloop::<T>);
+            .add_systems(
+                Update,
+                (
+                    gameloop::<T>,
+                    reconnect::rejoin::<T>
+                        .after(copied_map::init_clients::<T>)
+                        .before(kit::give_kits::<T>),
+                ),
+            );
         } else {
             app.add_plugins(map::MapPlugin::<T> {
                 phantom: PhantomData,
             })
-            .add_systems(Update, gameloop::<T>);
+            .add_systems(
+                Update,
+                (
+                    gameloop::<T>,
+                    reconnect::rejoin::<T>
+                        .after(map::init_clients::<T>)
+                        .before(kit::give_kits::<T>),
+                ),
+            );
         }
     }
 }
 
+#[derive(QueryData)]
+pub struct DisconnectQuery {
+    entity: Entity,
+    gamestate: &'static PlayerGameState,
+    unique_id: &'static UniqueId,
+    username: &'static Username,
+    pos: &'static Position,
+    look: &'static Look,
+    health: &'static Health,
+    game_mode: &'static GameMode,
+    inventory: &'static Inventory,
+    stats: Option<&'static MatchStats>,
+    rating: Option<&'static Rating>,
+}
+
 pub fn handle_disconnect(
-    disconncted: Query<(Entity, &PlayerGameState), Added<Despawned>>,
+    disconncted: Query<DisconnectQuery, Added<Despawned>>,
     mut clients: Query<(&mut Client, &PlayerGameState)>,
-    mut end_game: MessageWriter<EndGameMessage>,
+    mut games: Query<(&GameStage, &rounds::Rounds, &mut Leavers)>,
+    mut eliminations: MessageWriter<EliminationMessage>,
     mut globals: ResMut<ServerGlobals>,
+    mut reconnects: ResMut<Reconnects>,
+    server: Res<Server>,
+    settings: Res<GameSettings>,
 ) {
-    for (entity, dc_gamestate) in disconncted.iter() {
-        if globals.queue.contains(&entity) {
-            globals.queue.retain(|&x| x != entity);
-        } else {
+    for player in disconncted.iter() {
+        if globals.queue.contains(&player.entity) {
+            globals.queue.retain(|&x| x != player.entity);
+        } else if let Some(game_id) = player.gamestate.game_id {
+            let grace = settings.reconnect_grace_secs;
+            let round = match games.get_mut(game_id) {
+                Ok((stage, rounds, mut leavers)) if *stage != GameStage::Ended => {
+                    // Leaving loses the game, unless the player comes back in time
+                    if let Some(rating) = player.rating {
+                        leavers.add(
+                            player.unique_id.0.as_u128(),
+                            player.gamestate.team,
+                            rating.0,
+                        );
+                    }
+                    (grace > 0).then_some(rounds.round)
+                }
+                _ => None,
+            };
+
+            let message = match round {
+                Some(_) => {
+                    player.username.0.clone().color(Color::GOLD)
+                        + " disconnected! They have ".color(Color::GRAY)
+                        + grace.to_string().color(Color::GOLD)
+                        + " seconds to reconnect.".color(Color::GRAY)
+                }
+                None => "A player disconnected!".into_text(),
+            };
             for (mut client, gamestate) in clients.iter_mut() {
-                if gamestate.game_id == dc_gamestate.game_id {
-                    client.send_chat_message("Your opponent disconnected!");
+                if gamestate.game_id == player.gamestate.game_id {
+                    client.send_chat_message(message.clone());
                     client.clear_title();
                 }
             }
+
+            let Some(round) = round else {
+                eliminations.write(EliminationMessage {
+                    game_id,
+                    player: player.entity,
+                });
+                continue;
+            };
+            reconnects.0.insert(
+                player.unique_id.0.as_u128(),
+                PlayerSnapshot {
+                    entity: player.entity,
+                    username: player.username.0.clone(),
+                    game_id,
+                    team: player.gamestate.team,
+                    team_slot: player.gamestate.team_slot,
+                    wins: player.gamestate.wins,
+                    eliminated: player.gamestate.eliminated,
+                    last_map: player.gamestate.last_map,
+                    round,
+                    pos: player.pos.0,
+                    yaw: player.look.yaw,
+                    pitch: player.look.pitch,
+                    health: player.health.0,
+                    game_mode: *player.game_mode,
+                    slots: (0..player.inventory.slot_count())
+                        .map(|slot| player.inventory.slot(slot).clone())
+                        .collect(),
+                    stats: player.stats.cloned().unwrap_or_default(),
+                    expires: server.current_tick() + grace as i64 * 20,
+                },
+            );
+        }
+    }
+}
+
+// Runs in PreUpdate so that game modes can react to the resulting EndGameMessage in Update
+// before the game entity is despawned in PostUpdate.
+pub fn handle_elimination<T: Resource + DuelsConfig>(
+    mut players: Query<
+        (
+            &mut PlayerGameState,
+            &mut GameMode,
+            &mut Position,
+            &mut Look,
+            &mut HeadYaw,
+        ),
+        Without<Despawned>,
+    >,
+    games: Query<(&Entities, &MapIndex)>,
+    mut eliminations: MessageReader<EliminationMessage>,
+    mut round_end: MessageWriter<RoundEndMessage>,
+    mut end_game: MessageWriter<EndGameMessage>,
+    reconnects: Res<Reconnects>,
+    config: Res<T>,
+) {
+    let mut ended = Vec::new();
+    for message in eliminations.read() {
+        if ended.contains(&message.game_id) {
+            continue;
+        }
+        // Players that are still connected can't be eliminated while the game is paused, but
+        // the disconnected ones forfeit when their time is up
+        if reconnects.is_paused(message.game_id) && players.contains(message.player) {
+            continue;
+        }
+        // Players are only eliminated once, e.g. while they stay outside a sudden death border
+        if players
+            .get(message.player)
+            .is_ok_and(|(gamestate, ..)| gamestate.eliminated)
+        {
+            continue;
+        }
+        let Ok((entities, map)) = games.get(message.game_id) else {
+            continue;
+        };
+        if let Ok((mut gamestate, mut game_mode, mut pos, mut look, mut yaw)) =
+            players.get_mut(message.player)
+            && gamestate.game_id == Some(message.game_id)
+            && !gamestate.eliminated
+        {
+            gamestate.eliminated = true;
+            *game_mode = GameMode::Spectator;
+
+            let spawn = config.worlds()[map.0].player_spawn(gamestate.team, gamestate.team_slot);
+            pos.set(spawn.pos);
+            look.yaw = spawn.rot[0];
+            look.pitch = spawn.rot[1];
+            yaw.0 = spawn.rot[0];
+        }
+
+        let mut alive_teams = Vec::new();
+        let mut connected_teams = Vec::new();
+        for entity in entities.0.iter() {
+            let Ok((gamestate, ..)) = players.get(*entity) else {
+                continue;
+            };
+            if !connected_teams.contains(&gamestate.team) {
+                connected_teams.push(gamestate.team);
+            }
+            if !gamestate.eliminated && !alive_teams.contains(&gamestate.team) {
+                alive_teams.push(gamestate.team);
+            }
+        }
+        // Players that can still reconnect keep their team in the game
+        for snapshot in reconnects.in_game(message.game_id) {
+            if !connected_teams.contains(&snapshot.team) {
+                connected_teams.push(snapshot.team);
+            }
+            if !snapshot.eliminated && !alive_teams.contains(&snapshot.team) {
+                alive_teams.push(snapshot.team);
+            }
+        }
+        if connected_teams.len() <= 1 {
+            // Every other team left the game, so it can't continue for another round
+            ended.push(message.game_id);
             end_game.write(EndGameMessage {
-                game_id: dc_gamestate.game_id.unwrap(),
-                loser: dc_gamestate.team,
+                game_id: message.game_id,
+                result: connected_teams
+                    .first()
+                    .map_or(GameResult::Draw, |team| GameResult::Winner(*team)),
+            });
+        } else if alive_teams.len() <= 1 {
+            ended.push(message.game_id);
+            round_end.write(RoundEndMessage {
+                game_id: message.game_id,
+                // Nobody wins if every team was eliminated at once
+                result: alive_teams
+                    .first()
+                    .map_or(GameResult::Draw, |team| GameResult::Winner(*team)),
             });
         }
     }
 }
 
+/// Fails on startup instead of mid-game if a map doesn't have a spawn for every player.
+fn check_spawns<T: Resource + DuelsConfig>(config: Res<T>, settings: Res<GameSettings>) {
+    // The waiting area doesn't have teams
+    for world in config.worlds().iter().skip(1) {
+        if world.spawns.len() < settings.teams as usize {
+            panic!(
+                "{} has {} spawns but there are {} teams",
+                world.display_name(),
+                world.spawns.len(),
+                settings.teams
+            );
+        }
+        for (team, spawn) in world.spawns.iter().take(settings.teams as usize).enumerate() {
+            if spawn.teammates.len() + 1 < settings.team_size {
+                panic!(
+                    "The spawn of team {} on {} needs {} teammates for teams of {}",
+                    team,
+                    world.display_name(),
+                    settings.team_size - 1,
+                    settings.team_size
+                );
+            }
+        }
+    }
+}
+
+/// Spawns a game for `players`, which are split into teams in order. The map is voted on while
+/// waiting unless one is given.
+pub fn spawn_game(
+    commands: &mut Commands,
+    server: &Server,
+    settings: &GameSettings,
+    players: Vec<Entity>,
+    map: Option<usize>,
+) -> Entity {
+    let entitylayer = commands.spawn(EntityLayer::new(server)).id();
+
+    commands
+        .spawn(Game {
+            map: MapIndex(map.unwrap_or(0)),
+            layer: EntityLayerId(entitylayer),
+            clients: Entities(players),
+            game_start: GameTime::new(server.current_tick()),
+            game_stage: GameStage::Waiting,
+            rounds: rounds::Rounds::new(settings.teams),
+            votes: MapVotes::default(),
+            leavers: Leavers::default(),
+        })
+        .id()
+}
+
 pub fn start_game(
-    mut clients: Query<&mut CombatState, With<Client>>,
+    mut clients: Query<(&mut PlayerGameState, &mut CombatState), With<Client>>,
     games: Query<&Entities>,
     mut start_game: MessageReader<StartGameMessage>,
-    mut gamestage: MessageWriter<GameStageMessage>,
+    mut stage_enter: MessageWriter<StageEnterMessage>,
+    settings: Res<GameSettings>,
 ) {
     for event in start_game.read() {
         if let Ok(entities) = games.get(event.0) {
-            for entity in entities.0.iter() {
-                if let Ok(mut state) = clients.get_mut(*entity) {
-                    *state = CombatState::default();
+            for (i, entity) in entities.0.iter().enumerate() {
+                if let Ok((mut gamestate, mut combat_state)) = clients.get_mut(*entity) {
+                    gamestate.game_id = Some(event.0);
+                    gamestate.team = (i % settings.teams as usize) as u8;
+                    gamestate.team_slot = (i / settings.teams as usize) as u8;
+                    gamestate.eliminated = false;
+                    *combat_state = CombatState::default();
                 }
             }
         }
-        gamestage.write(GameStageMessage {
+        stage_enter.write(StageEnterMessage {
             game_id: event.0,
-            stage: 0,
+            stage: GameStage::Waiting,
         });
     }
 }
 
+/// Effects and hunger don't carry over into the next round or out of a game.
+pub fn reset_status(
+    mut players: Query<(Option<&mut StatusEffects>, Option<&mut Hunger>)>,
+    games: Query<&Entities>,
+    mut round_start: MessageReader<RoundStartMessage>,
+    mut end_game: MessageReader<EndGameMessage>,
+) {
+    let game_ids: Vec<Entity> = round_start
+        .read()
+        .map(|message| message.game_id)
+        .chain(end_game.read().map(|message| message.game_id))
+        .collect();
+    for game_id in game_ids {
+        let Ok(entities) = games.get(game_id) else {
+            continue;
+        };
+        for entity in entities.0.iter() {
+            let Ok((effects, hunger)) = players.get_mut(*entity) else {
+                continue;
+            };
+            if let Some(mut effects) = effects {
+                effects.clear();
+            }
+            if let Some(mut hunger) = hunger {
+                hunger.reset();
+            }
+        }
+    }
+}
+
+pub fn end_stage(
+    mut games: Query<(&mut GameStage, &mut GameTime)>,
+    mut end_game: MessageReader<EndGameMessage>,
+    mut stage_exit: MessageWriter<StageExitMessage>,
+    mut stage_enter: MessageWriter<StageEnterMessage>,
+    server: Res<Server>,
+) {
+    for event in end_game.read() {
+        if let Ok((mut stage, mut time)) = games.get_mut(event.game_id)
+            && *stage != GameStage::Ended
+        {
+            change_stage(
+                event.game_id,
+                &mut stage,
+                &mut time,
+                GameStage::Ended,
+                server.current_tick(),
+                &mut stage_exit,
+                &mut stage_enter,
+            );
+        }
+    }
+}
+
+pub fn change_stage(
+    game_id: Entity,
+    stage: &mut GameStage,
+    time: &mut GameTime,
+    next: GameStage,
+    tick: i64,
+    stage_exit: &mut MessageWriter<StageExitMessage>,
+    stage_enter: &mut MessageWriter<StageEnterMessage>,
+) {
+    stage_exit.write(StageExitMessage {
+        game_id,
+        stage: *stage,
+    });
+    *stage = next;
+    time.stage_start = tick;
+    stage_enter.write(StageEnterMessage {
+        game_id,
+        stage: next,
+    });
+}
+
 #[derive(QueryData)]
 #[query_data(mutable)]
 pub struct GameQuery {
@@ -198,15 +879,94 @@ pub struct GameQuery {
 
 pub fn gameloop<T: Resource + DuelsConfig>(
     mut clients: Query<GameQuery>,
-    mut games: Query<(Entity, &Entities, &MapIndex, &mut GameStage, &GameTime)>,
-    mut gamestage: MessageWriter<GameStageMessage>,
+    mut games: Query<(
+        Entity,
+        &Entities,
+        &MapIndex,
+        &mut GameStage,
+        &mut GameTime,
+        Option<&mut time_limit::SuddenDeath>,
+    )>,
+    mut new_rounds: MessageReader<NewRoundMessage>,
+    mut stage_exit: MessageWriter<StageExitMessage>,
+    mut stage_enter: MessageWriter<StageEnterMessage>,
+    mut countdown: MessageWriter<CountdownMessage>,
+    reconnects: Res<Reconnects>,
+    server: Res<Server>,
+    settings: Res<GameSettings>,
     config: Res<T>,
 ) {
-    for (game_id, entities, map, mut stage, time) in games.iter_mut() {
-        if stage.0 < 4 {
+    let tick = server.current_tick();
+
+    for NewRoundMessage(game_id) in new_rounds.read() {
+        if let Ok((.., mut stage, mut time)) = games.get_mut(*game_id)
+            && *stage == GameStage::Playing
+        {
+            change_stage(
+                *game_id,
+                &mut stage,
+                &mut time,
+                GameStage::RoundOver,
+                tick,
+                &mut stage_exit,
+                &mut stage_enter,
+            );
+        }
+    }
+
+    let countdown_ticks = settings.countdown_secs as i64 * 20;
+    let round_over_ticks = settings.round_over_secs as i64 * 20;
+
+    for (game_id, entities, map, mut stage, mut time, sudden_death) in games.iter_mut() {
+        // Timers are pushed back for as long as the game is paused
+        let paused = reconnects.is_paused(game_id);
+        if paused {
+            time.stage_start += 1;
+            if let Some(mut sudden_death) = sudden_death {
+                sudden_death.since += 1;
+            }
+        }
+
+        let elapsed = time.stage_elapsed(tick);
+        let next = match *stage {
+            _ if paused => None,
+            GameStage::Waiting if map.0 != 0 => Some(GameStage::Countdown),
+            GameStage::Countdown if elapsed >= countdown_ticks => Some(GameStage::Playing),
+            GameStage::RoundOver if elapsed >= round_over_ticks => Some(GameStage::Countdown),
+            _ => None,
+        };
+        if let Some(next) = next {
+            change_stage(
+                game_id,
+                &mut stage,
+                &mut time,
+                next,
+                tick,
+                &mut stage_exit,
+                &mut stage_enter,
+            );
+        }
+        if *stage == GameStage::Playing && !paused {
+            time.played += 1;
+        }
+
+        let elapsed = time.stage_elapsed(tick);
+        if *stage == GameStage::Countdown
+            && !paused
+            && elapsed % 20 == 0
+            && elapsed < countdown_ticks
+        {
+            countdown.write(CountdownMessage {
+                game_id,
+                seconds: settings.countdown_secs - (elapsed / 20) as u32,
+            });
+        }
+
+        if stage.is_frozen() && map.0 != 0 {
             for entity in entities.0.iter() {
                 if let Ok(mut player) = clients.get_mut(*entity) {
-                    let spawn = &config.worlds()[map.0].spawns[player.gamestate.team as usize % 2];
+                    let spawn = config.worlds()[map.0]
+                        .player_spawn(player.gamestate.team, player.gamestate.team_slot);
                     player.pos.set(spawn.pos);
                     player.look.yaw = spawn.rot[0];
                     player.look.pitch = spawn.rot[1];
@@ -214,51 +974,68 @@ pub fn gameloop<T: Resource + DuelsConfig>(
                 }
             }
         }
-        if (stage.0 < 5 && time.0.elapsed().unwrap().as_secs() >= stage.0 as u64) || stage.0 == 0 {
-            stage.0 += 1;
-            gamestage.write(GameStageMessage {
-                game_id,
-                stage: stage.0,
-            });
-        }
     }
 }
 
-pub fn gamestage_change(
+pub fn stage_titles(
     mut clients: Query<(&mut Client, &Position)>,
-    games: Query<&Entities>,
-    mut gamestage: MessageReader<GameStageMessage>,
+    games: Query<(&Entities, &GameStage, &GameTime)>,
+    mut countdown: MessageReader<CountdownMessage>,
+    mut stage_enter: MessageReader<StageEnterMessage>,
+    server: Res<Server>,
 ) {
-    for event in gamestage.read() {
-        if let Ok(entities) = games.get(event.game_id) {
-            for entity in entities.0.iter() {
-                if let Ok((mut client, pos)) = clients.get_mut(*entity) {
-                    match event.stage {
-                        1 => client.set_title("3".color(Color::GREEN)),
-                        2 => client.set_title("2".color(Color::GOLD)),
-                        3 => client.set_title("1".color(Color::RED)),
-                        4 => client.set_title("GO!".color(Color::RED)),
-                        5 => client.clear_title(),
-                        _ => {}
-                    }
-                    if event.stage < 4 {
-                        client.play_sound(
-                            Sound::BlockNoteBlockPling,
-                            SoundCategory::Master,
-                            pos.0,
-                            1.0,
-                            1.0,
-                        );
-                    } else if event.stage == 4 {
-                        client.play_sound(
-                            Sound::BlockNoteBlockPling,
-                            SoundCategory::Master,
-                            pos.0,
-                            1.0,
-                            5.0,
-                        );
-                    }
-                }
+    for event in countdown.read() {
+        let Ok((entities, ..)) = games.get(event.game_id) else {
+            continue;
+        };
+        let color = match event.seconds {
+            1 => Color::RED,
+            2 => Color::GOLD,
+            _ => Color::GREEN,
+        };
+        for entity in entities.0.iter() {
+            if let Ok((mut client, pos)) = clients.get_mut(*entity) {
+                client.set_title(event.seconds.to_string().color(color));
+                client.play_sound(
+                    Sound::BlockNoteBlockPling,
+                    SoundCategory::Master,
+                    pos.0,
+                    1.0,
+                    1.0,
+                );
+            }
+        }
+    }
+
+    for event in stage_enter.read() {
+        if event.stage != GameStage::Playing {
+            continue;
+        }
+        let Ok((entities, ..)) = games.get(event.game_id) else {
+            continue;
+        };
+        for entity in entities.0.iter() {
+            if let Ok((mut client, pos)) = clients.get_mut(*entity) {
+                client.set_title("GO!".color(Color::RED));
+                client.play_sound(
+                    Sound::BlockNoteBlockPling,
+                    SoundCategory::Master,
+                    pos.0,
+                    1.0,
+                    5.0,
+                );
+            }
+        }
+    }
+
+    // Clear the "GO!" title after a second
+    for (entities, stage, time) in games.iter() {
+        if *stage != GameStage::Playing || time.stage_elapsed(server.current_tick()) != 20 {
+            continue;
+        }
+        for entity in entities.0.iter() {
+            if let Ok((mut client, _)) = clients.get_mut(*entity) {
+                client.clear_title();
             }
         }
     }
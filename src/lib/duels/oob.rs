@@ -1,11 +1,11 @@
 use crate::death::DeathMessage;
-use crate::duels::{EndGameMessage, PlayerGameState};
+use crate::duels::{EliminationMessage, PlayerGameState};
 use chunkedge::prelude::*;
 use std::ops::RangeBounds;
 
 pub enum OobMode {
     DeathEvent,
-    GameEndEvent,
+    Eliminate,
 }
 
 #[derive(Resource)]
@@ -35,7 +35,7 @@ where
 
         match self.mode {
             OobMode::DeathEvent => app.add_systems(Update, handle_oob_clients_death::<R>),
-            OobMode::GameEndEvent => app.add_systems(Update, handle_oob_clients_end_game::<R>),
+            OobMode::Eliminate => app.add_systems(Update, handle_oob_clients_eliminate::<R>),
         };
     }
 }
@@ -54,20 +54,21 @@ fn handle_oob_clients_death<R>(
     }
 }
 
-fn handle_oob_clients_end_game<R>(
-    positions: Query<(&Position, &PlayerGameState), With<Client>>,
-    mut end_game: MessageWriter<EndGameMessage>,
+fn handle_oob_clients_eliminate<R>(
+    positions: Query<(Entity, &Position, &PlayerGameState), With<Client>>,
+    mut eliminations: MessageWriter<EliminationMessage>,
     oob: Res<OobResource<R>>,
 ) where
     R: RangeBounds<f64> + Send + Sync + Clone + 'static,
 {
-    for (pos, gamestate) in positions.iter() {
+    for (entity, pos, gamestate) in positions.iter() {
         if !oob.bounds_y.contains(&pos.y)
+            && !gamestate.eliminated
             && let Some(game_id) = gamestate.game_id
         {
-            end_game.write(EndGameMessage {
+            eliminations.write(EliminationMessage {
                 game_id,
-                loser: gamestate.team,
+                player: entity,
             });
         }
     }
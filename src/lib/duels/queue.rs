@@ -0,0 +1,124 @@
+#![allow(clippy::type_complexity)]
+
+use super::*;
+use super::kit_editor::KitEditor;
+use super::matchmaking::Matchmaker;
+use crate::color::format;
+use crate::scoreboard::{ScoreboardId, gen_scores};
+use chunkedge::{
+    event_loop::PacketMessage,
+    inventory::HeldItem,
+    item::ItemComponent,
+    protocol::{IntoTextComponent, packets::play::UseItemC2s},
+    scoreboard::{Objective, ObjectiveScores},
+};
+
+/// Hotbar slot of the leave queue item
+const LEAVE_ITEM_SLOT: u16 = 43;
+/// Server the proxy sends players to when they leave the queue
+const LOBBY_SERVER: &str = "lobby";
+
+/// Shows queued players where they are in the queue and lets them leave it
+pub struct QueueStatusPlugin;
+
+impl Plugin for QueueStatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(EventLoopUpdate, leave_item_interactions)
+            .add_systems(Update, (update_queue_status, give_leave_item));
+    }
+}
+
+fn update_queue_status(
+    mut clients: Query<(&mut Client, Option<&ScoreboardId>)>,
+    gamestates: Query<&PlayerGameState, With<Client>>,
+    mut objectives: Query<&mut ObjectiveScores, With<Objective>>,
+    globals: Res<ServerGlobals>,
+    matchmaker: Res<Matchmaker>,
+    server: Res<Server>,
+) {
+    let tick = server.current_tick();
+    if tick % 20 != 0 {
+        return;
+    }
+    let queued = globals.queue.len();
+    let in_game = gamestates
+        .iter()
+        .filter(|gamestate| gamestate.game_id.is_some())
+        .count();
+
+    for (i, entity) in globals.queue.iter().enumerate() {
+        let Ok((mut client, scoreboard_id)) = clients.get_mut(*entity) else {
+            continue;
+        };
+        let secs = matchmaker.waited(*entity, tick).unwrap_or(0) / 20;
+        let waited = format!("{}:{:02}", secs / 60, secs % 60);
+
+        client.set_action_bar(
+            "Queue ".color(Color::GRAY)
+                + format!("#{}/{}", i + 1, queued).color(Color::GOLD)
+                + " | In game ".color(Color::GRAY)
+                + in_game.to_string().color(Color::GOLD)
+                + " | Waiting ".color(Color::GRAY)
+                + waited.clone().color(Color::GOLD),
+        );
+
+        if let Some(scoreboard_id) = scoreboard_id
+            && let Ok(mut scores) = objectives.get_mut(scoreboard_id.0)
+        {
+            *scores = gen_scores(&[
+                format::WHITE.to_string() + "Position: " + format::GOLD + &(i + 1).to_string(),
+                format::WHITE.to_string() + "Queued: " + format::GOLD + &queued.to_string(),
+                format::WHITE.to_string() + "In game: " + format::GOLD + &in_game.to_string(),
+                format::WHITE.to_string() + "Waiting: " + format::GOLD + &waited,
+            ]);
+        }
+    }
+}
+
+/// Gives queued players the leave queue item and takes it away from everyone else.
+fn give_leave_item(
+    mut clients: Query<(Entity, &mut Inventory), (With<Client>, Without<KitEditor>)>,
+    globals: Res<ServerGlobals>,
+) {
+    for (entity, mut inv) in clients.iter_mut() {
+        if globals.queue.contains(&entity) {
+            if inv.slot(LEAVE_ITEM_SLOT).is_empty() {
+                inv.set_slot(
+                    LEAVE_ITEM_SLOT,
+                    ItemStack::new(ItemKind::RedBed, 1).with_components(vec![
+                        ItemComponent::ItemName("Leave queue".into_text_component()),
+                    ]),
+                );
+            }
+        } else if inv.slot(LEAVE_ITEM_SLOT).item == ItemKind::RedBed {
+            inv.set_slot(LEAVE_ITEM_SLOT, ItemStack::EMPTY);
+        }
+    }
+}
+
+fn leave_item_interactions(
+    mut clients: Query<(&mut Client, &Inventory, &HeldItem, &Username)>,
+    mut packets: MessageReader<PacketMessage>,
+    mut globals: ResMut<ServerGlobals>,
+) {
+    for packet in packets.read() {
+        if let Some(_pkt) = packet.decode::<UseItemC2s>()
+            && let Ok((mut client, inv, held_item, username)) = clients.get_mut(packet.client)
+            && held_item.slot() == LEAVE_ITEM_SLOT
+            && inv.slot(LEAVE_ITEM_SLOT).item == ItemKind::RedBed
+            && globals.queue.contains(&packet.client)
+        {
+            globals.queue.retain(|entity| *entity != packet.client);
+            client.send_chat_message("Sending you to the lobby...".color(Color::GRAY));
+
+            // Asks the proxy to move the player, the same way the lobby's warps do
+            let mut payload: Vec<u8> = Vec::new();
+            payload.extend_from_slice("1".as_bytes());
+            payload.push(0);
+            payload.extend_from_slice(username.0.as_bytes());
+            payload.push(0);
+            payload.extend_from_slice(LOBBY_SERVER.as_bytes());
+            client.send_custom_payload(ident!("minibit:main"), &payload);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+#![allow(clippy::type_complexity)]
+#![allow(clippy::too_many_arguments)]
+
+use super::*;
+use super::matchmaking::{Leavers, Rating};
+use bevy_ecs::query::QueryData;
+use bevy_ecs::system::ParamSet;
+
+/// Everything needed to put a disconnected player back into their game
+pub struct PlayerSnapshot {
+    /// The entity the player had before disconnecting, which is still listed in the game
+    pub entity: Entity,
+    pub username: String,
+    pub game_id: Entity,
+    pub team: u8,
+    pub team_slot: u8,
+    pub wins: u32,
+    pub eliminated: bool,
+    pub last_map: Option<usize>,
+    /// The round the player disconnected in
+    pub round: u32,
+    pub pos: DVec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub health: f32,
+    pub game_mode: GameMode,
+    pub slots: Vec<ItemStack>,
+    pub stats: stats::MatchStats,
+    /// Tick at which the player forfeits
+    pub expires: i64,
+}
+
+/// Players that can still reconnect to their game, keyed by UUID
+#[derive(Resource, Default)]
+pub struct Reconnects(pub HashMap<u128, PlayerSnapshot>);
+
+impl Reconnects {
+    /// The disconnected players of a game that still count as being in it.
+    pub fn in_game(&self, game_id: Entity) -> impl Iterator<Item = &PlayerSnapshot> {
+        self.0
+            .values()
+            .filter(move |snapshot| snapshot.game_id == game_id)
+    }
+
+    /// Whether a game is on hold until its disconnected players are back or forfeit. Its timers
+    /// stand still, and nobody can score, take damage or be eliminated.
+    pub fn is_paused(&self, game_id: Entity) -> bool {
+        self.in_game(game_id).next().is_some()
+    }
+}
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub struct RejoinQuery {
+    entity: Entity,
+    unique_id: &'static UniqueId,
+    client: &'static mut Client,
+    layer_id: &'static mut EntityLayerId,
+    visible_chunk_layer: &'static mut VisibleChunkLayer,
+    visible_entity_layers: &'static mut VisibleEntityLayers,
+    pos: &'static mut Position,
+    look: &'static mut Look,
+    yaw: &'static mut HeadYaw,
+    health: &'static mut Health,
+    game_mode: &'static mut GameMode,
+    inventory: &'static mut Inventory,
+}
+
+/// Puts players that reconnected during their grace period back into their game. Runs after the
+/// map plugin has added them to the queue.
+pub fn rejoin<T: Resource + DuelsConfig>(
+    mut clients: ParamSet<(
+        Query<RejoinQuery, Added<Client>>,
+        Query<(&mut Client, &PlayerGameState)>,
+    )>,
+    mut games: Query<
+        (
+            &mut Entities,
+            &EntityLayerId,
+            &MapIndex,
+            &rounds::Rounds,
+            &mut Leavers,
+            Option<&MapLayer>,
+        ),
+        Without<Client>,
+    >,
+    mut give_kit: MessageWriter<kit::GiveKitMessage>,
+    mut commands: Commands,
+    mut reconnects: ResMut<Reconnects>,
+    mut server_globals: ResMut<ServerGlobals>,
+    queue_layer: Res<QueueLayer>,
+    settings: Res<GameSettings>,
+    config: Res<T>,
+) {
+    let mut rejoined = Vec::new();
+    for mut player in clients.p0().iter_mut() {
+        let Some(mut snapshot) = reconnects.0.remove(&player.unique_id.0.as_u128()) else {
+            continue;
+        };
+        let Ok((mut entities, game_layer, map, rounds, mut leavers, map_layer)) =
+            games.get_mut(snapshot.game_id)
+        else {
+            continue;
+        };
+        if let Some(rating) = leavers.take(player.unique_id.0.as_u128()) {
+            commands.entity(player.entity).insert(Rating(rating));
+        }
+
+        for entity in entities.0.iter_mut() {
+            if *entity == snapshot.entity {
+                *entity = player.entity;
+            }
+        }
+        server_globals.queue.retain(|entity| *entity != player.entity);
+
+        // The player missed the end of their round, so they start the current one fresh
+        let new_round = rounds.round != snapshot.round;
+        if new_round {
+            let spawn = config.worlds()[map.0].player_spawn(snapshot.team, snapshot.team_slot);
+            snapshot.eliminated = false;
+            snapshot.health = 20.0;
+            snapshot.game_mode = settings.default_gamemode;
+            snapshot.pos = DVec3::from_array(spawn.pos);
+            snapshot.yaw = spawn.rot[0];
+            snapshot.pitch = spawn.rot[1];
+        }
+
+        // Players are only moved onto the map once it has been chosen
+        if let Some(map_layer) = map_layer {
+            player.visible_entity_layers.0.remove(&queue_layer.0);
+            player.visible_entity_layers.0.insert(game_layer.0);
+            player.layer_id.0 = game_layer.0;
+            player.visible_chunk_layer.0 = map_layer.0;
+        }
+        player.pos.set(snapshot.pos);
+        player.look.yaw = snapshot.yaw;
+        player.look.pitch = snapshot.pitch;
+        player.yaw.0 = snapshot.yaw;
+        player.health.0 = snapshot.health;
+        *player.game_mode = snapshot.game_mode;
+        if new_round {
+            for slot in 0..player.inventory.slot_count() {
+                player.inventory.set_slot(slot, ItemStack::EMPTY);
+            }
+            give_kit.write(kit::GiveKitMessage(player.entity));
+        } else {
+            for (slot, stack) in snapshot.slots.into_iter().enumerate() {
+                player.inventory.set_slot(slot as u16, stack);
+            }
+        }
+        player.client.send_chat_message("You reconnected to your game!".color(Color::GREEN));
+
+        commands.entity(player.entity).insert((
+            PlayerGameState {
+                game_id: Some(snapshot.game_id),
+                team: snapshot.team,
+                team_slot: snapshot.team_slot,
+                wins: snapshot.wins,
+                eliminated: snapshot.eliminated,
+                last_map: snapshot.last_map,
+            },
+            CombatState::default(),
+            snapshot.stats,
+        ));
+        rejoined.push((snapshot.game_id, snapshot.username));
+    }
+
+    for (game_id, username) in rejoined {
+        for (mut client, gamestate) in clients.p1().iter_mut() {
+            if gamestate.game_id == Some(game_id) {
+                client.send_chat_message(
+                    username.clone().color(Color::GOLD) + " reconnected!".color(Color::GRAY),
+                );
+            }
+        }
+    }
+}
+
+/// Forfeits players that didn't reconnect in time.
+pub fn expire_reconnects(
+    mut clients: Query<(&mut Client, &PlayerGameState)>,
+    games: Query<&GameStage>,
+    mut eliminations: MessageWriter<EliminationMessage>,
+    mut reconnects: ResMut<Reconnects>,
+    server: Res<Server>,
+) {
+    let tick = server.current_tick();
+    reconnects.0.retain(|_, snapshot| {
+        let Ok(stage) = games.get(snapshot.game_id) else {
+            return false;
+        };
+        if *stage == GameStage::Ended {
+            return false;
+        }
+        if snapshot.expires > tick {
+            return true;
+        }
+
+        for (mut client, gamestate) in clients.iter_mut() {
+            if gamestate.game_id == Some(snapshot.game_id) {
+                client.send_chat_message(
+                    snapshot.username.clone().color(Color::GOLD)
+                        + " didn't reconnect in time.".color(Color::GRAY),
+                );
+            }
+        }
+        eliminations.write(EliminationMessage {
+            game_id: snapshot.game_id,
+            player: snapshot.entity,
+        });
+        false
+    });
+}
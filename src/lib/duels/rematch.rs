@@ -0,0 +1,252 @@
+#![allow(clippy::type_complexity)]
+#![allow(clippy::too_many_arguments)]
+
+use super::*;
+use super::matchmaking::{QueueHold, Unranked};
+use super::spectate::Spectating;
+use crate::scopes::ScopePlugin;
+use chunkedge::{
+    command::{AddCommand, handler::CommandResultMessage},
+    command_macros::Command,
+    event_loop::PacketMessage,
+    inventory::HeldItem,
+    item::ItemComponent,
+    protocol::{IntoTextComponent, packets::play::UseItemC2s},
+};
+
+/// Hotbar slot of the rematch item
+const REMATCH_ITEM_SLOT: u16 = 38;
+
+#[derive(Command, Debug, Clone)]
+#[paths("rematch")]
+#[scopes("minibit.commands.all.rematch")]
+struct RematchCommand {}
+
+#[derive(Message)]
+struct RematchAcceptMessage(Entity);
+
+struct RematchOffer {
+    /// The game that just ended
+    game_id: Entity,
+    /// Players in the order they were split into teams
+    players: Vec<Entity>,
+    accepted: Vec<Entity>,
+    map: usize,
+    unranked: bool,
+    expires: i64,
+}
+
+#[derive(Resource, Default)]
+struct RematchOffers(Vec<RematchOffer>);
+
+pub struct RematchPlugin;
+
+impl Plugin for RematchPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<ScopePlugin>() {
+            app.add_plugins(ScopePlugin);
+        }
+        app.insert_resource(RematchOffers::default())
+            .add_message::<RematchAcceptMessage>()
+            .add_command::<RematchCommand>()
+            .add_systems(EventLoopUpdate, item_interactions)
+            .add_systems(
+                Update,
+                (
+                    create_offers,
+                    handle_rematch_command,
+                    accept_rematch
+                        .after(handle_rematch_command)
+                        .after(create_offers),
+                    update_offers.after(accept_rematch),
+                ),
+            );
+    }
+}
+
+fn create_offers(
+    mut clients: Query<&mut Client>,
+    games: Query<(&Entities, &MapIndex, Has<Unranked>)>,
+    mut end_game: MessageReader<EndGameMessage>,
+    mut commands: Commands,
+    mut offers: ResMut<RematchOffers>,
+    server: Res<Server>,
+    settings: Res<GameSettings>,
+) {
+    if settings.rematch_secs == 0 {
+        return;
+    }
+    for event in end_game.read() {
+        let Ok((entities, map, unranked)) = games.get(event.game_id) else {
+            continue;
+        };
+        // Everyone has to still be online for a rematch
+        if map.0 == 0 || entities.0.iter().any(|entity| !clients.contains(*entity)) {
+            continue;
+        }
+
+        for entity in entities.0.iter() {
+            if let Ok(mut client) = clients.get_mut(*entity) {
+                client.send_chat_message(
+                    "Play again? ".color(Color::GRAY)
+                        + "[Rematch]"
+                            .color(Color::GREEN)
+                            .bold()
+                            .on_click_run_command("/rematch"),
+                );
+            }
+            commands.entity(*entity).insert(QueueHold);
+        }
+
+        offers.0.push(RematchOffer {
+            game_id: event.game_id,
+            players: entities.0.clone(),
+            accepted: Vec::new(),
+            map: map.0,
+            unranked,
+            expires: server.current_tick() + settings.rematch_secs as i64 * 20,
+        });
+    }
+}
+
+fn handle_rematch_command(
+    mut command_results: MessageReader<CommandResultMessage<RematchCommand>>,
+    mut accept: MessageWriter<RematchAcceptMessage>,
+) {
+    for message in command_results.read() {
+        accept.write(RematchAcceptMessage(message.executor));
+    }
+}
+
+fn item_interactions(
+    clients: Query<(&Inventory, &HeldItem), With<QueueHold>>,
+    mut packets: MessageReader<PacketMessage>,
+    mut accept: MessageWriter<RematchAcceptMessage>,
+) {
+    for packet in packets.read() {
+        if let Some(_pkt) = packet.decode::<UseItemC2s>()
+            && let Ok((inv, held_item)) = clients.get(packet.client)
+            && held_item.slot() == REMATCH_ITEM_SLOT
+            && inv.slot(REMATCH_ITEM_SLOT).item == ItemKind::Emerald
+        {
+            accept.write(RematchAcceptMessage(packet.client));
+        }
+    }
+}
+
+fn accept_rematch(
+    mut clients: Query<(&mut Client, &mut Inventory, &Username)>,
+    mut accept: MessageReader<RematchAcceptMessage>,
+    mut start_game: MessageWriter<StartGameMessage>,
+    mut commands: Commands,
+    mut offers: ResMut<RematchOffers>,
+    mut globals: ResMut<ServerGlobals>,
+    server: Res<Server>,
+    settings: Res<GameSettings>,
+) {
+    for RematchAcceptMessage(player) in accept.read() {
+        let Some(i) = offers
+            .0
+            .iter()
+            .position(|offer| offer.players.contains(player))
+        else {
+            if let Ok((mut client, ..)) = clients.get_mut(*player) {
+                client.send_chat_message("You don't have a rematch to accept.".color(Color::RED));
+            }
+            continue;
+        };
+        let offer = &mut offers.0[i];
+        if offer.accepted.contains(player) {
+            continue;
+        }
+        offer.accepted.push(*player);
+
+        let Ok((_, mut inv, username)) = clients.get_mut(*player) else {
+            continue;
+        };
+        if inv.slot(REMATCH_ITEM_SLOT).item == ItemKind::Emerald {
+            inv.set_slot(REMATCH_ITEM_SLOT, ItemStack::EMPTY);
+        }
+        let text = username.0.clone().color(Color::GOLD)
+            + " wants a rematch (".color(Color::GRAY)
+            + offer.accepted.len().to_string().color(Color::GOLD)
+            + "/".color(Color::GRAY)
+            + offer.players.len().to_string().color(Color::GOLD)
+            + ")".color(Color::GRAY);
+        for entity in offer.players.iter() {
+            if let Ok((mut client, ..)) = clients.get_mut(*entity) {
+                client.send_chat_message(text.clone());
+            }
+        }
+
+        if offer.accepted.len() < offer.players.len() {
+            continue;
+        }
+        let offer = offers.0.remove(i);
+        globals
+            .queue
+            .retain(|entity| !offer.players.contains(entity));
+        for entity in offer.players.iter() {
+            commands.entity(*entity).try_remove::<QueueHold>();
+        }
+
+        let map = settings.rematch_same_map.then_some(offer.map);
+        let game_id = spawn_game(&mut commands, &server, &settings, offer.players, map);
+        if offer.unranked {
+            commands.entity(game_id).insert(Unranked);
+        }
+        start_game.write(StartGameMessage(game_id));
+    }
+}
+
+/// Shows the rematch item and cancels offers that expired or that a player can no longer accept.
+fn update_offers(
+    mut clients: Query<(&mut Client, &mut Inventory, &PlayerGameState, Has<Spectating>)>,
+    mut commands: Commands,
+    mut offers: ResMut<RematchOffers>,
+    server: Res<Server>,
+) {
+    let tick = server.current_tick();
+    offers.0.retain(|offer| {
+        let available = offer.players.iter().all(|entity| {
+            clients
+                .get(*entity)
+                .is_ok_and(|(_, _, gamestate, spectating)| {
+                    // Players are only sent back to the queue once the game has been cleaned up
+                    (gamestate.game_id.is_none() || gamestate.game_id == Some(offer.game_id))
+                        && !spectating
+                })
+        });
+        if available && offer.expires > tick {
+            for entity in offer.players.iter() {
+                if offer.accepted.contains(entity) {
+                    continue;
+                }
+                if let Ok((_, mut inv, ..)) = clients.get_mut(*entity)
+                    && inv.slot(REMATCH_ITEM_SLOT).is_empty()
+                {
+                    inv.set_slot(
+                        REMATCH_ITEM_SLOT,
+                        ItemStack::new(ItemKind::Emerald, 1).with_components(vec![
+                            ItemComponent::ItemName("Rematch".into_text_component()),
+                        ]),
+                    );
+                }
+            }
+            return true;
+        }
+
+        for entity in offer.players.iter() {
+            commands.entity(*entity).try_remove::<QueueHold>();
+            if let Ok((mut client, mut inv, gamestate, _)) = clients.get_mut(*entity) {
+                if inv.slot(REMATCH_ITEM_SLOT).item == ItemKind::Emerald {
+                    inv.set_slot(REMATCH_ITEM_SLOT, ItemStack::EMPTY);
+                }
+                if gamestate.game_id.is_none() || gamestate.game_id == Some(offer.game_id) {
+                    client.send_chat_message("The rematch offer expired.".color(Color::GRAY));
+                }
+            }
+        }
+        false
+    });
+}
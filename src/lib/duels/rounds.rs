@@ -0,0 +1,241 @@
+#![allow(clippy::type_complexity)]
+
+use super::*;
+use chunkedge::entity::living::Absorption;
+
+#[derive(Component)]
+pub struct Rounds {
+    pub round: u32,
+    pub wins: Vec<u32>,
+}
+
+impl Rounds {
+    pub fn new(teams: u8) -> Self {
+        Self {
+            round: 0,
+            wins: vec![0; teams as usize],
+        }
+    }
+}
+
+/// Awards the current round to the winning team. The game ends once a team reaches the mode's
+/// rounds to win or when the game is out of time. Drawn rounds are played again.
+#[derive(Message)]
+pub struct RoundEndMessage {
+    pub game_id: Entity,
+    pub result: GameResult,
+}
+
+/// Sent after players have been reset at the start of every round's countdown.
+#[derive(Message)]
+pub struct RoundStartMessage {
+    pub game_id: Entity,
+    pub round: u32,
+}
+
+/// What happens to a game after one of its rounds ended
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RoundOutcome {
+    NextRound,
+    GameOver(GameResult),
+}
+
+/// Awards the round to its winner, if any, and decides whether the game goes on. Once the time
+/// is up, the team with the most round wins takes the game.
+pub fn round_outcome(
+    wins: &mut [u32],
+    result: GameResult,
+    rounds_to_win: u32,
+    time_up: bool,
+) -> RoundOutcome {
+    if let Some(winner) = result.winner().filter(|winner| (*winner as usize) < wins.len()) {
+        wins[winner as usize] += 1;
+        if wins[winner as usize] >= rounds_to_win {
+            return RoundOutcome::GameOver(result);
+        }
+    }
+    if !time_up {
+        return RoundOutcome::NextRound;
+    }
+    let most = wins.iter().copied().max().unwrap_or(0);
+    let leaders: Vec<usize> = (0..wins.len()).filter(|team| wins[*team] == most).collect();
+    RoundOutcome::GameOver(match leaders[..] {
+        [team] => GameResult::Winner(team as u8),
+        _ => GameResult::Draw,
+    })
+}
+
+pub fn handle_round_end(
+    mut clients: Query<&mut Client>,
+    mut games: Query<(&Entities, &GameStage, &GameTime, &mut Rounds)>,
+    mut round_end: MessageReader<RoundEndMessage>,
+    mut new_rounds: MessageWriter<NewRoundMessage>,
+    mut end_game: MessageWriter<EndGameMessage>,
+    settings: Res<GameSettings>,
+) {
+    let mut ended = Vec::new();
+    for event in round_end.read() {
+        if ended.contains(&event.game_id) {
+            continue;
+        }
+        let Ok((entities, stage, time, mut rounds)) = games.get_mut(event.game_id) else {
+            continue;
+        };
+        if *stage != GameStage::Playing {
+            continue;
+        }
+        ended.push(event.game_id);
+
+        let outcome = round_outcome(
+            &mut rounds.wins,
+            event.result,
+            settings.rounds_to_win,
+            settings.is_time_up(time),
+        );
+        if let RoundOutcome::GameOver(result) = outcome {
+            end_game.write(EndGameMessage {
+                game_id: event.game_id,
+                result,
+            });
+            continue;
+        }
+
+        let title = match event.result {
+            GameResult::Winner(winner) => {
+                team_name(winner).color(team_color(winner)) + " won the round!".color(Color::GRAY)
+            }
+            GameResult::Draw => "The round is a draw!".color(Color::GRAY),
+        };
+        let subtitle = score_line(&rounds.wins);
+        for entity in entities.0.iter() {
+            if let Ok(mut client) = clients.get_mut(*entity) {
+                client.set_title(title.clone());
+                client.set_subtitle(subtitle.clone());
+            }
+        }
+
+        new_rounds.write(NewRoundMessage(event.game_id));
+    }
+}
+
+pub fn start_round(
+    mut clients: Query<(
+        &mut PlayerGameState,
+        &mut CombatState,
+        &mut GameMode,
+        &mut Health,
+        &mut Absorption,
+        &mut Inventory,
+    )>,
+    mut games: Query<(&Entities, &mut Rounds)>,
+    mut stage_enter: MessageReader<StageEnterMessage>,
+    mut round_start: MessageWriter<RoundStartMessage>,
+    settings: Res<GameSettings>,
+) {
+    for event in stage_enter.read() {
+        if event.stage != GameStage::Countdown {
+            continue;
+        }
+        let Ok((entities, mut rounds)) = games.get_mut(event.game_id) else {
+            continue;
+        };
+        rounds.round += 1;
+
+        for entity in entities.0.iter() {
+            let Ok((
+                mut gamestate,
+                mut combat_state,
+                mut game_mode,
+                mut health,
+                mut absorption,
+                mut inv,
+            )) = clients.get_mut(*entity)
+            else {
+                continue;
+            };
+            gamestate.eliminated = false;
+            *combat_state = CombatState::default();
+            *game_mode = settings.default_gamemode;
+            health.0 = 20.0;
+            absorption.0 = 0.0;
+            for slot in 0..inv.slot_count() {
+                inv.set_slot(slot, ItemStack::EMPTY);
+            }
+        }
+
+        round_start.write(RoundStartMessage {
+            game_id: event.game_id,
+            round: rounds.round,
+        });
+    }
+}
+
+/// Formats the round wins of every team, e.g. "Blue 2 - Red 1".
+pub fn score_line(wins: &[u32]) -> Text {
+    let mut text = Text::from(String::new());
+    for (team, team_wins) in wins.iter().enumerate() {
+        if team > 0 {
+            text = text + " - ".color(Color::GRAY);
+        }
+        text = text
+            + (String::from(team_name(team as u8)) + " " + &team_wins.to_string())
+                .color(team_color(team as u8));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reaching_the_rounds_to_win_ends_the_game() {
+        let mut wins = vec![1, 0];
+        let outcome = round_outcome(&mut wins, GameResult::Winner(0), 2, false);
+        assert_eq!(outcome, RoundOutcome::GameOver(GameResult::Winner(0)));
+        assert_eq!(wins, [2, 0]);
+    }
+
+    #[test]
+    fn the_game_goes_on_until_a_team_has_enough_wins() {
+        let mut wins = vec![0, 0];
+        let outcome = round_outcome(&mut wins, GameResult::Winner(1), 2, false);
+        assert_eq!(outcome, RoundOutcome::NextRound);
+        assert_eq!(wins, [0, 1]);
+    }
+
+    #[test]
+    fn drawn_rounds_are_replayed() {
+        let mut wins = vec![1, 1];
+        let outcome = round_outcome(&mut wins, GameResult::Draw, 2, false);
+        assert_eq!(outcome, RoundOutcome::NextRound);
+        assert_eq!(wins, [1, 1]);
+    }
+
+    #[test]
+    fn the_most_round_wins_take_the_game_once_time_is_up() {
+        let mut wins = vec![2, 1];
+        let outcome = round_outcome(&mut wins, GameResult::Draw, 5, true);
+        assert_eq!(outcome, RoundOutcome::GameOver(GameResult::Winner(0)));
+
+        // The last round counts before the wins are compared
+        let mut wins = vec![2, 2];
+        let outcome = round_outcome(&mut wins, GameResult::Winner(1), 5, true);
+        assert_eq!(outcome, RoundOutcome::GameOver(GameResult::Winner(1)));
+    }
+
+    #[test]
+    fn tied_round_wins_are_a_draw_once_time_is_up() {
+        let mut wins = vec![1, 1, 0];
+        let outcome = round_outcome(&mut wins, GameResult::Draw, 5, true);
+        assert_eq!(outcome, RoundOutcome::GameOver(GameResult::Draw));
+    }
+
+    #[test]
+    fn unknown_teams_win_nothing() {
+        let mut wins = vec![0, 0];
+        let outcome = round_outcome(&mut wins, GameResult::Winner(2), 1, false);
+        assert_eq!(outcome, RoundOutcome::NextRound);
+        assert_eq!(wins, [0, 0]);
+    }
+}
@@ -0,0 +1,415 @@
+#![allow(clippy::type_complexity)]
+#![allow(clippy::too_many_arguments)]
+
+use super::*;
+use super::kit_editor::KitEditor;
+use crate::scopes::ScopePlugin;
+use bevy_ecs::query::QueryData;
+use chunkedge::{
+    command::{AddCommand, handler::CommandResultMessage},
+    command_macros::Command,
+    event_loop::PacketMessage,
+    inventory::{ClickSlotMessage, HeldItem},
+    item::ItemComponent,
+    protocol::{
+        IntoTextComponent,
+        packets::play::{SpectatorTeleportC2s, UseItemC2s},
+    },
+};
+
+/// Hotbar slot of the item that opens the spectator menu in the waiting area
+const SPECTATE_ITEM_SLOT: u16 = 44;
+
+#[derive(Command, Debug, Clone)]
+#[paths("spectate {player?}", "spec {player?}")]
+#[scopes("minibit.commands.all.spectate")]
+struct SpectateCommand {
+    player: Option<String>,
+}
+
+/// The game a player is watching
+#[derive(Component)]
+pub struct Spectating(pub Entity);
+
+#[derive(Message)]
+pub struct StartSpectatingMessage {
+    pub viewer: Entity,
+    pub game_id: Entity,
+}
+
+#[derive(Message)]
+pub struct StopSpectatingMessage(pub Entity);
+
+#[derive(Resource)]
+pub struct SpectateGlobals {
+    pub menu: Entity,
+    /// The game shown in each slot of the menu
+    pub listed: Vec<Entity>,
+    /// Layer that spectators' own entities are put on so that nobody can see them
+    pub hidden_layer: Entity,
+}
+
+pub struct SpectatePlugin<T: Resource + DuelsConfig> {
+    pub phantom: PhantomData<T>,
+}
+
+impl<T: Resource + DuelsConfig> Plugin for SpectatePlugin<T> {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<ScopePlugin>() {
+            app.add_plugins(ScopePlugin);
+        }
+        app.add_message::<StartSpectatingMessage>()
+            .add_message::<StopSpectatingMessage>()
+            .add_command::<SpectateCommand>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                EventLoopUpdate,
+                (item_interactions, handle_menu_click, spectator_teleport),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_spectate_command,
+                    update_menu,
+                    give_spectate_item.after(start_game),
+                    start_spectating::<T>
+                        .after(handle_spectate_command)
+                        .after(update_menu),
+                    stop_spectating::<T>.after(handle_spectate_command),
+                ),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, server: Res<Server>) {
+    let mut menu = Inventory::with_title(InventoryKind::Generic9x3, "Spectate a game");
+    menu.readonly = true;
+    let menu = commands.spawn(menu).id();
+    let hidden_layer = commands.spawn(EntityLayer::new(&server)).id();
+
+    commands.insert_resource(SpectateGlobals {
+        menu,
+        listed: Vec::new(),
+        hidden_layer,
+    });
+}
+
+fn handle_spectate_command(
+    mut clients: Query<&mut Client>,
+    players: Query<(Entity, &Username, &PlayerGameState, Option<&Spectating>)>,
+    mut command_results: MessageReader<CommandResultMessage<SpectateCommand>>,
+    mut start: MessageWriter<StartSpectatingMessage>,
+    mut stop: MessageWriter<StopSpectatingMessage>,
+    mut commands: Commands,
+    globals: Res<SpectateGlobals>,
+) {
+    for message in command_results.read() {
+        let Ok((_, _, _, spectating)) = players.get(message.executor) else {
+            continue;
+        };
+        let Some(name) = &message.result.player else {
+            if spectating.is_some() {
+                stop.write(StopSpectatingMessage(message.executor));
+            } else {
+                commands
+                    .entity(message.executor)
+                    .insert(OpenInventory::new(globals.menu));
+            }
+            continue;
+        };
+
+        let game_id = players
+            .iter()
+            .find(|(_, username, ..)| username.0.eq_ignore_ascii_case(name))
+            .and_then(|(_, _, gamestate, spectating)| {
+                gamestate.game_id.or(spectating.map(|spectating| spectating.0))
+            });
+        match game_id {
+            Some(game_id) => {
+                start.write(StartSpectatingMessage {
+                    viewer: message.executor,
+                    game_id,
+                });
+            }
+            None => {
+                if let Ok(mut client) = clients.get_mut(message.executor) {
+                    client.send_chat_message("That player isn't in a game.".color(Color::RED));
+                }
+            }
+        }
+    }
+}
+
+fn update_menu(
+    usernames: Query<(&Username, &PlayerGameState)>,
+    games: Query<(Entity, &Entities, &GameStage), With<MapLayer>>,
+    mut inventories: Query<&mut Inventory, Without<Client>>,
+    mut globals: ResMut<SpectateGlobals>,
+    server: Res<Server>,
+) {
+    if server.current_tick() % 20 != 0 {
+        return;
+    }
+    let Ok(mut menu) = inventories.get_mut(globals.menu) else {
+        return;
+    };
+
+    let mut listed = Vec::new();
+    for (game_id, entities, stage) in games.iter() {
+        if *stage == GameStage::Ended || listed.len() >= menu.slot_count() as usize {
+            continue;
+        }
+        let mut teams: Vec<Vec<String>> = Vec::new();
+        for entity in entities.0.iter() {
+            if let Ok((username, gamestate)) = usernames.get(*entity) {
+                let team = gamestate.team as usize;
+                if teams.len() <= team {
+                    teams.resize(team + 1, Vec::new());
+                }
+                teams[team].push(username.0.clone());
+            }
+        }
+        let name = teams
+            .iter()
+            .filter(|team| !team.is_empty())
+            .map(|team| team.join(", "))
+            .collect::<Vec<_>>()
+            .join(" vs ");
+
+        menu.set_slot(
+            listed.len() as u16,
+            ItemStack::new(ItemKind::EnderEye, 1)
+                .with_components(vec![ItemComponent::ItemName(name.into_text_component())]),
+        );
+        listed.push(game_id);
+    }
+    for slot in listed.len() as u16..menu.slot_count() {
+        if !menu.slot(slot).is_empty() {
+            menu.set_slot(slot, ItemStack::EMPTY);
+        }
+    }
+
+    globals.listed = listed;
+}
+
+fn give_spectate_item(
+    mut clients: Query<
+        (&mut Inventory, &PlayerGameState),
+        (With<Client>, Without<Spectating>, Without<KitEditor>),
+    >,
+    mut start_game: MessageReader<StartGameMessage>,
+    games: Query<&Entities>,
+) {
+    for event in start_game.read() {
+        let Ok(entities) = games.get(event.0) else {
+            continue;
+        };
+        for entity in entities.0.iter() {
+            if let Ok((mut inv, _)) = clients.get_mut(*entity)
+                && inv.slot(SPECTATE_ITEM_SLOT).item == ItemKind::EnderEye
+            {
+                inv.set_slot(SPECTATE_ITEM_SLOT, ItemStack::EMPTY);
+            }
+        }
+    }
+
+    for (mut inv, gamestate) in clients.iter_mut() {
+        if gamestate.game_id.is_none() && inv.slot(SPECTATE_ITEM_SLOT).is_empty() {
+            inv.set_slot(
+                SPECTATE_ITEM_SLOT,
+                ItemStack::new(ItemKind::EnderEye, 1).with_components(vec![
+                    ItemComponent::ItemName("Spectate a game".into_text_component()),
+                ]),
+            );
+        }
+    }
+}
+
+fn item_interactions(
+    clients: Query<(&Inventory, &HeldItem, &PlayerGameState), (With<Client>, Without<Spectating>)>,
+    mut packets: MessageReader<PacketMessage>,
+    mut commands: Commands,
+    globals: Res<SpectateGlobals>,
+) {
+    for packet in packets.read() {
+        if let Some(_pkt) = packet.decode::<UseItemC2s>()
+            && let Ok((inv, held_item, gamestate)) = clients.get(packet.client)
+            && gamestate.game_id.is_none()
+            && held_item.slot() == SPECTATE_ITEM_SLOT
+            && inv.slot(SPECTATE_ITEM_SLOT).item == ItemKind::EnderEye
+        {
+            commands
+                .entity(packet.client)
+                .insert(OpenInventory::new(globals.menu));
+        }
+    }
+}
+
+fn handle_menu_click(
+    clients: Query<&OpenInventory, With<Client>>,
+    mut click_slot: MessageReader<ClickSlotMessage>,
+    mut start: MessageWriter<StartSpectatingMessage>,
+    globals: Res<SpectateGlobals>,
+) {
+    for message in click_slot.read() {
+        if let Ok(open_inv) = clients.get(message.client)
+            && open_inv.entity == globals.menu
+            && message.window_id.0 != 0
+            && message.slot_id >= 0
+            && let Some(game_id) = globals.listed.get(message.slot_id as usize)
+        {
+            start.write(StartSpectatingMessage {
+                viewer: message.client,
+                game_id: *game_id,
+            });
+        }
+    }
+}
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+struct ViewerQuery {
+    client: &'static mut Client,
+    gamestate: &'static PlayerGameState,
+    layer_id: &'static mut EntityLayerId,
+    visible_chunk_layer: &'static mut VisibleChunkLayer,
+    visible_entity_layers: &'static mut VisibleEntityLayers,
+    pos: &'static mut Position,
+    game_mode: &'static mut GameMode,
+    inventory: &'static mut Inventory,
+    spectating: Option<&'static Spectating>,
+}
+
+fn start_spectating<T: Resource + DuelsConfig>(
+    mut viewers: Query<ViewerQuery>,
+    games: Query<(&EntityLayerId, &MapLayer, &MapIndex, &GameStage), Without<Client>>,
+    mut start: MessageReader<StartSpectatingMessage>,
+    mut commands: Commands,
+    mut server_globals: ResMut<ServerGlobals>,
+    globals: Res<SpectateGlobals>,
+    queue_layer: Res<QueueLayer>,
+    config: Res<T>,
+) {
+    for event in start.read() {
+        let Ok(mut viewer) = viewers.get_mut(event.viewer) else {
+            continue;
+        };
+        if viewer.gamestate.game_id.is_some() {
+            viewer
+                .client
+                .send_chat_message("You can't spectate while playing.".color(Color::RED));
+            continue;
+        }
+        let Ok((game_layer, map_layer, map, stage)) = games.get(event.game_id) else {
+            viewer
+                .client
+                .send_chat_message("That game hasn't started yet.".color(Color::RED));
+            continue;
+        };
+        if *stage == GameStage::Ended {
+            continue;
+        }
+
+        let old_layer = viewer
+            .spectating
+            .and_then(|spectating| games.get(spectating.0).ok())
+            .map_or(queue_layer.0, |(old_layer, ..)| old_layer.0);
+        viewer.visible_entity_layers.0.remove(&old_layer);
+        viewer.visible_entity_layers.0.insert(game_layer.0);
+        viewer.layer_id.0 = globals.hidden_layer;
+        viewer.visible_chunk_layer.0 = map_layer.0;
+        *viewer.game_mode = GameMode::Spectator;
+        viewer.pos.set(config.worlds()[map.0].team_spawn(0).pos);
+        for slot in 0..viewer.inventory.slot_count() {
+            viewer.inventory.set_slot(slot, ItemStack::EMPTY);
+        }
+        viewer.client.send_chat_message(
+            "You are now spectating. Use ".color(Color::GRAY)
+                + "/spectate".color(Color::GOLD)
+                + " to leave.".color(Color::GRAY),
+        );
+
+        server_globals.queue.retain(|entity| *entity != event.viewer);
+        commands
+            .entity(event.viewer)
+            .insert(Spectating(event.game_id))
+            .remove::<OpenInventory>();
+    }
+}
+
+fn stop_spectating<T: Resource + DuelsConfig>(
+    mut viewers: Query<(Entity, ViewerQuery)>,
+    games: Query<&EntityLayerId, Without<Client>>,
+    mut end_game: MessageReader<EndGameMessage>,
+    mut stop: MessageReader<StopSpectatingMessage>,
+    mut commands: Commands,
+    mut server_globals: ResMut<ServerGlobals>,
+    queue_layer: Res<QueueLayer>,
+    settings: Res<GameSettings>,
+    config: Res<T>,
+) {
+    let mut stopping: Vec<Entity> = stop.read().map(|event| event.0).collect();
+    for event in end_game.read() {
+        for (entity, viewer) in viewers.iter() {
+            if viewer.spectating.is_some_and(|spectating| spectating.0 == event.game_id) {
+                stopping.push(entity);
+            }
+        }
+    }
+
+    for entity in stopping {
+        let Ok((_, mut viewer)) = viewers.get_mut(entity) else {
+            continue;
+        };
+        let Some(game_id) = viewer.spectating.map(|spectating| spectating.0) else {
+            continue;
+        };
+        if let Ok(game_layer) = games.get(game_id) {
+            viewer.visible_entity_layers.0.remove(&game_layer.0);
+        }
+        viewer.visible_entity_layers.0.insert(queue_layer.0);
+        viewer.layer_id.0 = queue_layer.0;
+        viewer.visible_chunk_layer.0 = queue_layer.0;
+        *viewer.game_mode = settings.default_gamemode;
+        viewer.pos.set(config.worlds()[0].spawns[0].pos);
+
+        if !server_globals.queue.contains(&entity) {
+            server_globals.queue.push(entity);
+        }
+        commands.entity(entity).remove::<Spectating>();
+    }
+}
+
+/// Handles the vanilla spectator hotbar menu, only allowing teleports to players in the game.
+fn spectator_teleport(
+    viewers: Query<&Spectating>,
+    mut positions: Query<&mut Position>,
+    players: Query<(Entity, &UniqueId), With<Client>>,
+    games: Query<&Entities>,
+    mut packets: MessageReader<PacketMessage>,
+) {
+    for packet in packets.read() {
+        let Some(pkt) = packet.decode::<SpectatorTeleportC2s>() else {
+            continue;
+        };
+        let Ok(Spectating(game_id)) = viewers.get(packet.client) else {
+            continue;
+        };
+        let Ok(entities) = games.get(*game_id) else {
+            continue;
+        };
+        let Some(target) = players
+            .iter()
+            .find(|(entity, unique_id)| unique_id.0 == pkt.target && entities.0.contains(entity))
+            .map(|(entity, _)| entity)
+        else {
+            continue;
+        };
+        let Ok(target_pos) = positions.get(target).map(|pos| pos.0) else {
+            continue;
+        };
+        if let Ok(mut pos) = positions.get_mut(packet.client) {
+            pos.set(target_pos);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+#![allow(clippy::type_complexity)]
+
+use super::*;
+use crate::food::golden_apple::GoldenAppleEatenMessage;
+use crate::projectiles::ProjectileOwner;
+use crate::world::BlockPlaceMessage;
+
+/// Sent by game modes for every hit that went through, so that it shows up in the match summary
+#[derive(Message)]
+pub struct HitMessage {
+    pub attacker: Entity,
+    pub victim: Entity,
+    pub damage: f32,
+    /// Whether the hit was from a projectile instead of melee
+    pub projectile: bool,
+}
+
+/// What a player did during their current or last game
+#[derive(Component, Default, Clone)]
+pub struct MatchStats {
+    pub hits: u32,
+    pub damage_dealt: f32,
+    pub damage_taken: f32,
+    /// Melee hits landed since the player was last hit
+    pub combo: u32,
+    pub longest_combo: u32,
+    pub arrows_shot: u32,
+    pub arrows_hit: u32,
+    pub golden_apples: u32,
+    pub blocks_placed: u32,
+}
+
+impl MatchStats {
+    /// Every stat worth showing, leaving out the ones the mode doesn't use.
+    fn lines(&self) -> Vec<(&'static str, String)> {
+        let mut lines = vec![
+            ("Hits", self.hits.to_string()),
+            ("Damage dealt", format!("{:.1}", self.damage_dealt)),
+            ("Damage taken", format!("{:.1}", self.damage_taken)),
+            ("Longest combo", self.longest_combo.to_string()),
+        ];
+        if self.arrows_shot > 0 {
+            lines.push((
+                "Arrows hit",
+                format!("{}/{}", self.arrows_hit, self.arrows_shot),
+            ));
+        }
+        if self.golden_apples > 0 {
+            lines.push(("Golden apples", self.golden_apples.to_string()));
+        }
+        if self.blocks_placed > 0 {
+            lines.push(("Blocks placed", self.blocks_placed.to_string()));
+        }
+        lines
+    }
+}
+
+pub fn reset_stats(
+    games: Query<&Entities>,
+    mut start_game: MessageReader<StartGameMessage>,
+    mut commands: Commands,
+) {
+    for StartGameMessage(game_id) in start_game.read() {
+        if let Ok(entities) = games.get(*game_id) {
+            for entity in entities.0.iter() {
+                commands.entity(*entity).insert(MatchStats::default());
+            }
+        }
+    }
+}
+
+pub fn track_stats(
+    mut players: Query<(&PlayerGameState, &mut MatchStats)>,
+    arrows: Query<&ProjectileOwner, Added<ProjectileOwner>>,
+    mut hits: MessageReader<HitMessage>,
+    mut placed: MessageReader<BlockPlaceMessage>,
+    mut eaten: MessageReader<GoldenAppleEatenMessage>,
+) {
+    for hit in hits.read() {
+        if let Some(mut attacker) = in_game(&mut players, hit.attacker) {
+            attacker.damage_dealt += hit.damage;
+            if hit.projectile {
+                attacker.arrows_hit += 1;
+            } else {
+                attacker.hits += 1;
+                attacker.combo += 1;
+                attacker.longest_combo = attacker.longest_combo.max(attacker.combo);
+            }
+        }
+        if let Some(mut victim) = in_game(&mut players, hit.victim) {
+            victim.damage_taken += hit.damage;
+            victim.combo = 0;
+        }
+    }
+    for owner in arrows.iter() {
+        if let Some(mut shooter) = in_game(&mut players, owner.0) {
+            shooter.arrows_shot += 1;
+        }
+    }
+    for message in placed.read() {
+        if let Some(mut placer) = in_game(&mut players, message.client) {
+            placer.blocks_placed += 1;
+        }
+    }
+    for GoldenAppleEatenMessage(entity) in eaten.read() {
+        if let Some(mut eater) = in_game(&mut players, *entity) {
+            eater.golden_apples += 1;
+        }
+    }
+}
+
+/// The stats of a player that is currently in a game.
+fn in_game<'a>(
+    players: &'a mut Query<(&PlayerGameState, &mut MatchStats)>,
+    entity: Entity,
+) -> Option<Mut<'a, MatchStats>> {
+    players
+        .get_mut(entity)
+        .ok()
+        .filter(|(gamestate, _)| gamestate.game_id.is_some())
+        .map(|(_, stats)| stats)
+}
+
+/// Shows every player's stats in chat, with the details in a tooltip, and the result as a title.
+pub fn show_summary(
+    mut clients: Query<(&mut Client, &PlayerGameState, &Username, Option<&MatchStats>)>,
+    games: Query<&Entities>,
+    mut end_game: MessageReader<EndGameMessage>,
+) {
+    for event in end_game.read() {
+        let Ok(entities) = games.get(event.game_id) else {
+            continue;
+        };
+
+        let mut summary = vec!["Match Summary".color(Color::GOLD).bold()
+            + " (hover for details)".color(Color::GRAY)];
+        for entity in entities.0.iter() {
+            let Ok((_, gamestate, username, Some(stats))) = clients.get(*entity) else {
+                continue;
+            };
+            let mut details = username.0.clone().color(team_color(gamestate.team)).bold();
+            for (name, value) in stats.lines() {
+                details = details
+                    + "\n".into_text()
+                    + format!("{}: ", name).color(Color::GRAY)
+                    + value.color(Color::WHITE);
+            }
+            summary.push(
+                " ".into_text()
+                    + username
+                        .0
+                        .clone()
+                        .color(team_color(gamestate.team))
+                        .on_hover_show_text(details)
+                    + format!(
+                        " {} hits, {:.1} damage",
+                        stats.hits, stats.damage_dealt
+                    )
+                    .color(Color::GRAY),
+            );
+        }
+
+        for entity in entities.0.iter() {
+            let Ok((mut client, gamestate, _, stats)) = clients.get_mut(*entity) else {
+                continue;
+            };
+            let title = match event.result {
+                GameResult::Winner(team) if team == gamestate.team => {
+                    "Victory!".color(Color::GOLD).bold()
+                }
+                GameResult::Winner(_) => "Defeat".color(Color::RED).bold(),
+                GameResult::Draw => "Draw".color(Color::GRAY).bold(),
+            };
+            client.set_title(title);
+            if let Some(stats) = stats {
+                client.set_subtitle(
+                    format!(
+                        "{} hits, {:.1} damage, {} combo",
+                        stats.hits, stats.damage_dealt, stats.longest_combo
+                    )
+                    .color(Color::GRAY),
+                );
+            }
+            for line in summary.iter() {
+                client.send_chat_message(line.clone());
+            }
+        }
+    }
+}
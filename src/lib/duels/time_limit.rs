@@ -0,0 +1,288 @@
+#![allow(clippy::type_complexity)]
+
+use super::*;
+use crate::config::WorldValue;
+use chunkedge::entity::living::Absorption;
+use chunkedge::protocol::{VarInt, VarLong, WritePacket, packets::play::InitializeWorldBorderS2c};
+
+/// How long sudden death lasts before the game is called a draw
+const SUDDEN_DEATH_SECS: i64 = 60;
+/// How long the border takes to close in during sudden death
+const BORDER_SHRINK_SECS: i64 = 45;
+const MIN_BORDER_DIAMETER: f64 = 4.0;
+/// Space left around the spawns when the border appears
+const BORDER_MARGIN: f64 = 8.0;
+/// Vanilla's border, which is sent to lift the sudden death border again
+const DEFAULT_BORDER_DIAMETER: f64 = 59_999_968.0;
+const PORTAL_TELEPORT_BOUNDARY: i32 = 29_999_984;
+
+/// What happens once a game reaches its time limit
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeLimitOutcome {
+    /// The game ends in a draw
+    Draw,
+    /// The team with the most health left wins, or the game is a draw if teams are tied
+    MostHealth,
+    /// The world border closes in on the middle of the map, eliminating players outside of it
+    ShrinkingBorder,
+    /// Every player is left on half a heart, so the next hit decides the round
+    OneHitKills,
+}
+
+impl TimeLimitOutcome {
+    fn is_sudden_death(&self) -> bool {
+        matches!(
+            self,
+            TimeLimitOutcome::ShrinkingBorder | TimeLimitOutcome::OneHitKills
+        )
+    }
+}
+
+/// Inserted on a game that ran out of time and went into sudden death
+#[derive(Component)]
+pub struct SuddenDeath {
+    pub since: i64,
+}
+
+pub fn check_time_limit<T: Resource + DuelsConfig>(
+    mut clients: Query<(&mut Client, &PlayerGameState, &Health, &Absorption)>,
+    games: Query<(
+        Entity,
+        &Entities,
+        &MapIndex,
+        &GameStage,
+        &GameTime,
+        Option<&SuddenDeath>,
+    )>,
+    mut round_end: MessageWriter<RoundEndMessage>,
+    mut commands: Commands,
+    reconnects: Res<Reconnects>,
+    server: Res<Server>,
+    settings: Res<GameSettings>,
+    config: Res<T>,
+) {
+    if settings.time_limit_secs == 0 {
+        return;
+    }
+    let tick = server.current_tick();
+    let limit = settings.time_limit_secs as i64 * 20;
+
+    for (game_id, entities, map, stage, time, sudden_death) in games.iter() {
+        if *stage != GameStage::Playing || reconnects.is_paused(game_id) {
+            continue;
+        }
+        if let Some(sudden_death) = sudden_death {
+            if tick - sudden_death.since == SUDDEN_DEATH_SECS * 20 {
+                round_end.write(RoundEndMessage {
+                    game_id,
+                    result: GameResult::Draw,
+                });
+            }
+            continue;
+        }
+        if time.played != limit {
+            continue;
+        }
+
+        let outcome = settings.time_limit_outcome;
+        let subtitle = match outcome {
+            TimeLimitOutcome::Draw => "The game is a draw".color(Color::GRAY),
+            TimeLimitOutcome::MostHealth => "Most health wins".color(Color::GRAY),
+            TimeLimitOutcome::ShrinkingBorder => "The border is closing in".color(Color::RED),
+            TimeLimitOutcome::OneHitKills => "One hit kills".color(Color::RED),
+        };
+        let title = if outcome.is_sudden_death() {
+            "Sudden Death!".color(Color::RED).bold()
+        } else {
+            "Time's up!".color(Color::GOLD)
+        };
+        let (center, diameter) = border(&config.worlds()[map.0], settings.teams);
+        for entity in entities.0.iter() {
+            if let Ok((mut client, ..)) = clients.get_mut(*entity) {
+                client.set_title(title.clone());
+                client.set_subtitle(subtitle.clone());
+                if outcome == TimeLimitOutcome::ShrinkingBorder {
+                    client.write_packet(&InitializeWorldBorderS2c {
+                        x: center[0],
+                        z: center[1],
+                        old_diameter: diameter,
+                        new_diameter: MIN_BORDER_DIAMETER,
+                        duration_millis: VarLong(BORDER_SHRINK_SECS * 1000),
+                        portal_teleport_boundary: VarInt(PORTAL_TELEPORT_BOUNDARY),
+                        warning_blocks: VarInt(0),
+                        warning_time: VarInt(0),
+                    });
+                }
+            }
+        }
+
+        let mut health = vec![0.0; settings.teams as usize];
+        for entity in entities.0.iter() {
+            if let Ok((_, gamestate, player_health, absorption)) = clients.get(*entity)
+                && !gamestate.eliminated
+                && let Some(team_health) = health.get_mut(gamestate.team as usize)
+            {
+                *team_health += player_health.0 + absorption.0;
+            }
+        }
+        match time_limit_result(outcome, &health) {
+            Some(result) => {
+                round_end.write(RoundEndMessage { game_id, result });
+            }
+            None => {
+                commands.entity(game_id).insert(SuddenDeath { since: tick });
+            }
+        }
+    }
+}
+
+/// How a round that ran out of time ends, given the health every team has left. Sudden death
+/// doesn't end the round right away, so it has no result.
+pub fn time_limit_result(outcome: TimeLimitOutcome, health: &[f32]) -> Option<GameResult> {
+    match outcome {
+        TimeLimitOutcome::Draw => Some(GameResult::Draw),
+        TimeLimitOutcome::MostHealth => {
+            let most = health.iter().copied().fold(0.0, f32::max);
+            let leaders: Vec<usize> = (0..health.len())
+                .filter(|team| health[*team] == most)
+                .collect();
+            Some(match leaders[..] {
+                [team] => GameResult::Winner(team as u8),
+                _ => GameResult::Draw,
+            })
+        }
+        TimeLimitOutcome::ShrinkingBorder | TimeLimitOutcome::OneHitKills => None,
+    }
+}
+
+pub fn sudden_death<T: Resource + DuelsConfig>(
+    mut clients: Query<(&PlayerGameState, &Position, &mut Health, &mut Absorption), With<Client>>,
+    games: Query<(Entity, &Entities, &MapIndex, &GameStage, &SuddenDeath)>,
+    mut eliminations: MessageWriter<EliminationMessage>,
+    reconnects: Res<Reconnects>,
+    server: Res<Server>,
+    settings: Res<GameSettings>,
+    config: Res<T>,
+) {
+    for (game_id, entities, map, stage, sudden_death) in games.iter() {
+        if *stage != GameStage::Playing || reconnects.is_paused(game_id) {
+            continue;
+        }
+        let (center, start_diameter) = border(&config.worlds()[map.0], settings.teams);
+        let shrunk = ((server.current_tick() - sudden_death.since) as f64
+            / (BORDER_SHRINK_SECS * 20) as f64)
+            .min(1.0);
+        let radius = (start_diameter + (MIN_BORDER_DIAMETER - start_diameter) * shrunk) / 2.0;
+
+        for entity in entities.0.iter() {
+            let Ok((gamestate, pos, mut health, mut absorption)) = clients.get_mut(*entity) else {
+                continue;
+            };
+            if gamestate.eliminated {
+                continue;
+            }
+            match settings.time_limit_outcome {
+                TimeLimitOutcome::OneHitKills => {
+                    if health.0 > 1.0 {
+                        health.0 = 1.0;
+                    }
+                    if absorption.0 > 0.0 {
+                        absorption.0 = 0.0;
+                    }
+                }
+                TimeLimitOutcome::ShrinkingBorder => {
+                    if (pos.0.x - center[0]).abs() > radius || (pos.0.z - center[1]).abs() > radius
+                    {
+                        eliminations.write(EliminationMessage {
+                            game_id,
+                            player: *entity,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Lifts the sudden death border once the game is over.
+pub fn lift_border(
+    mut clients: Query<&mut Client>,
+    games: Query<&Entities, With<SuddenDeath>>,
+    mut stage_exit: MessageReader<StageExitMessage>,
+    settings: Res<GameSettings>,
+) {
+    for event in stage_exit.read() {
+        if event.stage != GameStage::Playing
+            || settings.time_limit_outcome != TimeLimitOutcome::ShrinkingBorder
+        {
+            continue;
+        }
+        let Ok(entities) = games.get(event.game_id) else {
+            continue;
+        };
+        for entity in entities.0.iter() {
+            if let Ok(mut client) = clients.get_mut(*entity) {
+                client.write_packet(&InitializeWorldBorderS2c {
+                    x: 0.0,
+                    z: 0.0,
+                    old_diameter: DEFAULT_BORDER_DIAMETER,
+                    new_diameter: DEFAULT_BORDER_DIAMETER,
+                    duration_millis: VarLong(0),
+                    portal_teleport_boundary: VarInt(PORTAL_TELEPORT_BOUNDARY),
+                    warning_blocks: VarInt(0),
+                    warning_time: VarInt(0),
+                });
+            }
+        }
+    }
+}
+
+/// The center of a map's team spawns and the diameter of a border around all of them.
+fn border(world: &WorldValue, teams: u8) -> ([f64; 2], f64) {
+    let spawns: Vec<[f64; 3]> = (0..teams).map(|team| world.team_spawn(team).pos).collect();
+    let center = [
+        spawns.iter().map(|pos| pos[0]).sum::<f64>() / spawns.len() as f64,
+        spawns.iter().map(|pos| pos[2]).sum::<f64>() / spawns.len() as f64,
+    ];
+    let reach = spawns
+        .iter()
+        .map(|pos| (pos[0] - center[0]).abs().max((pos[2] - center[1]).abs()))
+        .fold(0.0, f64::max);
+    (center, (reach + BORDER_MARGIN) * 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_ends_the_round_regardless_of_health() {
+        let result = time_limit_result(TimeLimitOutcome::Draw, &[20.0, 3.0]);
+        assert_eq!(result, Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn the_team_with_the_most_health_wins() {
+        let result = time_limit_result(TimeLimitOutcome::MostHealth, &[12.5, 30.0, 4.0]);
+        assert_eq!(result, Some(GameResult::Winner(1)));
+    }
+
+    #[test]
+    fn tied_health_is_a_draw() {
+        let result = time_limit_result(TimeLimitOutcome::MostHealth, &[15.0, 15.0]);
+        assert_eq!(result, Some(GameResult::Draw));
+
+        // Nobody is left standing
+        let result = time_limit_result(TimeLimitOutcome::MostHealth, &[0.0, 0.0]);
+        assert_eq!(result, Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn sudden_death_keeps_the_round_going() {
+        for outcome in [TimeLimitOutcome::ShrinkingBorder, TimeLimitOutcome::OneHitKills] {
+            assert!(outcome.is_sudden_death());
+            assert_eq!(time_limit_result(outcome, &[20.0, 10.0]), None);
+        }
+    }
+}
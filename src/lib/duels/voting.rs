@@ -0,0 +1,284 @@
+#![allow(clippy::type_complexity)]
+
+use super::*;
+use chunkedge::event_loop::PacketMessage;
+use chunkedge::inventory::{ClickSlotMessage, HeldItem};
+use chunkedge::item::ItemComponent;
+use chunkedge::protocol::IntoTextComponent;
+use chunkedge::protocol::packets::play::UseItemC2s;
+
+/// Hotbar slot of the item that reopens the vote menu
+const VOTE_ITEM_SLOT: u16 = 40;
+
+/// The map each player of a waiting game voted for
+#[derive(Component, Default)]
+pub struct MapVotes(pub HashMap<Entity, usize>);
+
+#[derive(Resource)]
+pub struct VoteGlobals {
+    pub menu: Entity,
+}
+
+/// The maps that can be played, skipping the waiting area and maps with a weight of 0.
+pub fn enabled_maps(worlds: &[WorldValue]) -> impl Iterator<Item = usize> + '_ {
+    (1..worlds.len()).filter(|map| worlds[*map].weight > 0)
+}
+
+/// Voting is skipped if it's disabled or there is only one map to choose from.
+pub fn voting_enabled(settings: &GameSettings, worlds: &[WorldValue]) -> bool {
+    settings.map_vote_secs > 0 && enabled_maps(worlds).count() > 1
+}
+
+/// Picks the map with the most votes. Without votes a weighted random map is picked, skipping
+/// the maps in `recent` unless no other map is left.
+pub fn choose_map(
+    worlds: &[WorldValue],
+    votes: &HashMap<Entity, usize>,
+    recent: &[usize],
+) -> usize {
+    let mut counts = vec![0; worlds.len()];
+    for map in votes.values() {
+        if let Some(count) = counts.get_mut(*map) {
+            *count += 1;
+        }
+    }
+    let maps: Vec<usize> = enabled_maps(worlds).collect();
+    let most_votes = maps.iter().map(|map| counts[*map]).max().unwrap_or(0);
+
+    let candidates: Vec<usize> = if most_votes > 0 {
+        maps.into_iter()
+            .filter(|map| counts[*map] == most_votes)
+            .collect()
+    } else {
+        let fresh: Vec<usize> = maps
+            .iter()
+            .copied()
+            .filter(|map| !recent.contains(map))
+            .collect();
+        if fresh.is_empty() { maps } else { fresh }
+    };
+
+    weighted_pick(worlds, &candidates)
+}
+
+fn weighted_pick(worlds: &[WorldValue], candidates: &[usize]) -> usize {
+    let total: u32 = candidates.iter().map(|map| worlds[*map].weight).sum();
+    if total == 0 {
+        return candidates[fastrand::usize(..candidates.len())];
+    }
+    let mut roll = fastrand::u32(..total);
+    for map in candidates {
+        if roll < worlds[*map].weight {
+            return *map;
+        }
+        roll -= worlds[*map].weight;
+    }
+    candidates[candidates.len() - 1]
+}
+
+pub fn setup<T: Resource + DuelsConfig>(mut commands: Commands, config: Res<T>) {
+    let maps: Vec<usize> = enabled_maps(config.worlds()).collect();
+    if maps.is_empty() {
+        panic!("Every map has a weight of 0, so there is nothing to play on");
+    }
+    let kind = match maps.len() {
+        0..=9 => InventoryKind::Generic9x1,
+        10..=18 => InventoryKind::Generic9x2,
+        19..=27 => InventoryKind::Generic9x3,
+        28..=36 => InventoryKind::Generic9x4,
+        37..=45 => InventoryKind::Generic9x5,
+        _ => InventoryKind::Generic9x6,
+    };
+    let mut menu = Inventory::with_title(kind, "Vote for a map");
+    menu.readonly = true;
+    for (slot, map) in maps.into_iter().enumerate() {
+        if slot as u16 >= menu.slot_count() {
+            break;
+        }
+        menu.set_slot(
+            slot as u16,
+            ItemStack::new(ItemKind::Map, 1).with_components(vec![ItemComponent::ItemName(
+                config.worlds()[map]
+                    .display_name()
+                    .to_string()
+                    .into_text_component(),
+            )]),
+        );
+    }
+
+    let menu = commands.spawn(menu).id();
+    commands.insert_resource(VoteGlobals { menu });
+}
+
+pub fn open_vote<T: Resource + DuelsConfig>(
+    mut clients: Query<(&mut Client, &mut Inventory)>,
+    games: Query<(&Entities, &MapIndex)>,
+    mut stage_enter: MessageReader<StageEnterMessage>,
+    mut commands: Commands,
+    globals: Res<VoteGlobals>,
+    settings: Res<GameSettings>,
+    config: Res<T>,
+) {
+    if !voting_enabled(&settings, config.worlds()) {
+        return;
+    }
+    for event in stage_enter.read() {
+        if event.stage != GameStage::Waiting {
+            continue;
+        }
+        let Ok((entities, map)) = games.get(event.game_id) else {
+            continue;
+        };
+        // The map was already chosen, e.g. by a duel request
+        if map.0 != 0 {
+            continue;
+        }
+        for entity in entities.0.iter() {
+            let Ok((mut client, mut inv)) = clients.get_mut(*entity) else {
+                continue;
+            };
+            inv.set_slot(
+                VOTE_ITEM_SLOT,
+                ItemStack::new(ItemKind::Map, 1).with_components(vec![ItemComponent::ItemName(
+                    "Vote for a map".into_text_component(),
+                )]),
+            );
+            client.send_chat_message(
+                "Vote for the next map! Voting ends in ".color(Color::GRAY)
+                    + settings.map_vote_secs.to_string().color(Color::GOLD)
+                    + " seconds.".color(Color::GRAY),
+            );
+            commands.entity(*entity).insert(OpenInventory::new(globals.menu));
+        }
+    }
+}
+
+pub fn vote_item_interactions(
+    clients: Query<(&Inventory, &HeldItem, &PlayerGameState), With<Client>>,
+    games: Query<(&GameStage, &MapIndex)>,
+    mut packets: MessageReader<PacketMessage>,
+    mut commands: Commands,
+    globals: Res<VoteGlobals>,
+) {
+    for packet in packets.read() {
+        if let Some(_pkt) = packet.decode::<UseItemC2s>()
+            && let Ok((inv, held_item, gamestate)) = clients.get(packet.client)
+            && held_item.slot() == VOTE_ITEM_SLOT
+            && inv.slot(VOTE_ITEM_SLOT).item == ItemKind::Map
+            && let Some(game_id) = gamestate.game_id
+            && let Ok((GameStage::Waiting, MapIndex(0))) = games.get(game_id)
+        {
+            commands
+                .entity(packet.client)
+                .insert(OpenInventory::new(globals.menu));
+        }
+    }
+}
+
+pub fn handle_vote_click<T: Resource + DuelsConfig>(
+    mut clients: Query<(&mut Client, &PlayerGameState, &OpenInventory)>,
+    mut games: Query<(&GameStage, &MapIndex, &mut MapVotes)>,
+    mut click_slot: MessageReader<ClickSlotMessage>,
+    mut commands: Commands,
+    globals: Res<VoteGlobals>,
+    config: Res<T>,
+) {
+    for message in click_slot.read() {
+        let Ok((mut client, gamestate, open_inv)) = clients.get_mut(message.client) else {
+            continue;
+        };
+        if open_inv.entity != globals.menu || message.window_id.0 == 0 {
+            continue;
+        }
+        if message.slot_id < 0 {
+            continue;
+        }
+        // The menu only lists enabled maps, in order
+        let Some(map) = enabled_maps(config.worlds()).nth(message.slot_id as usize) else {
+            continue;
+        };
+        let Some(game_id) = gamestate.game_id else {
+            continue;
+        };
+        let Ok((GameStage::Waiting, MapIndex(0), mut votes)) = games.get_mut(game_id) else {
+            continue;
+        };
+
+        votes.0.insert(message.client, map);
+        client.send_chat_message(
+            "You voted for ".color(Color::GRAY)
+                + config.worlds()[map].display_name().to_string().color(Color::GOLD),
+        );
+        commands.entity(message.client).remove::<OpenInventory>();
+    }
+}
+
+pub fn select_map<T: Resource + DuelsConfig>(
+    mut clients: Query<&mut Client>,
+    players: Query<&PlayerGameState>,
+    mut games: Query<(&Entities, &mut MapIndex, &GameStage, &GameTime, &MapVotes)>,
+    server: Res<Server>,
+    settings: Res<GameSettings>,
+    config: Res<T>,
+) {
+    let vote_ticks = if voting_enabled(&settings, config.worlds()) {
+        settings.map_vote_secs as i64 * 20
+    } else {
+        0
+    };
+
+    for (entities, mut map, stage, time, votes) in games.iter_mut() {
+        if *stage != GameStage::Waiting
+            || map.0 != 0
+            || time.stage_elapsed(server.current_tick()) < vote_ticks
+        {
+            continue;
+        }
+
+        let recent: Vec<usize> = entities
+            .0
+            .iter()
+            .filter_map(|entity| players.get(*entity).ok()?.last_map)
+            .collect();
+        map.0 = choose_map(config.worlds(), &votes.0, &recent);
+
+        if vote_ticks == 0 {
+            continue;
+        }
+        let text = "Playing on ".color(Color::GRAY)
+            + config.worlds()[map.0].display_name().to_string().color(Color::GOLD);
+        for entity in entities.0.iter() {
+            if let Ok(mut client) = clients.get_mut(*entity) {
+                client.send_chat_message(text.clone());
+            }
+        }
+    }
+}
+
+pub fn close_vote(
+    mut clients: Query<(Entity, &mut Inventory, Option<&OpenInventory>)>,
+    games: Query<&Entities>,
+    mut stage_exit: MessageReader<StageExitMessage>,
+    mut commands: Commands,
+    globals: Res<VoteGlobals>,
+) {
+    for event in stage_exit.read() {
+        if event.stage != GameStage::Waiting {
+            continue;
+        }
+        let Ok(entities) = games.get(event.game_id) else {
+            continue;
+        };
+        for entity in entities.0.iter() {
+            let Ok((entity, mut inv, open_inv)) = clients.get_mut(*entity) else {
+                continue;
+            };
+            if inv.slot(VOTE_ITEM_SLOT).item == ItemKind::Map {
+                inv.set_slot(VOTE_ITEM_SLOT, ItemStack::EMPTY);
+            }
+            if open_inv.is_some_and(|open_inv| open_inv.entity == globals.menu) {
+                commands.entity(entity).remove::<OpenInventory>();
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::death::DeathMessage;
+use crate::player::MAX_HEALTH;
+use chunkedge::entity::attributes::{EntityAttribute, EntityAttributes};
+use chunkedge::entity::living::{Absorption, Health};
+use chunkedge::prelude::*;
+use chunkedge::protocol::packets::play::entity_status_effect_s2c::Flags;
+use chunkedge::protocol::packets::play::{EntityStatusEffectS2c, RemoveEntityStatusEffectS2c};
+use chunkedge::protocol::{VarInt, WritePacket};
+use std::collections::{HashMap, HashSet};
+
+/// Vanilla's base movement speed of players
+const BASE_MOVEMENT_SPEED: f64 = 0.1;
+
+/// Effects that have an impact on gameplay. Their discriminants are vanilla's registry ids.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum StatusEffect {
+    Speed = 0,
+    Slowness = 1,
+    Strength = 4,
+    Regeneration = 9,
+    Resistance = 10,
+    Absorption = 21,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ActiveEffect {
+    /// The level of the effect minus one, so 0 is level I
+    pub amplifier: u8,
+    /// Ticks left until the effect ends
+    pub duration: i64,
+}
+
+/// The effects a player currently has. Changes are sent to the client on the next update.
+#[derive(Component, Default)]
+pub struct StatusEffects {
+    active: HashMap<StatusEffect, ActiveEffect>,
+    /// Effects that were added or removed since they were last sent to the client
+    changed: HashSet<StatusEffect>,
+    /// Effects that ended or were removed, and haven't been undone yet
+    removed: Vec<(StatusEffect, ActiveEffect)>,
+}
+
+impl StatusEffects {
+    /// Adds an effect like vanilla does, replacing the current one only if it is weaker or runs
+    /// out sooner at the same level.
+    pub fn add(&mut self, effect: StatusEffect, amplifier: u8, duration: i64) {
+        if let Some(current) = self.active.get(&effect)
+            && (current.amplifier > amplifier
+                || (current.amplifier == amplifier && current.duration >= duration))
+        {
+            return;
+        }
+        self.active.insert(
+            effect,
+            ActiveEffect {
+                amplifier,
+                duration,
+            },
+        );
+        self.changed.insert(effect);
+    }
+
+    pub fn remove(&mut self, effect: StatusEffect) {
+        if let Some(active) = self.active.remove(&effect) {
+            self.removed.push((effect, active));
+            self.changed.insert(effect);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let effects: Vec<StatusEffect> = self.active.keys().copied().collect();
+        for effect in effects {
+            self.remove(effect);
+        }
+    }
+
+    pub fn get(&self, effect: StatusEffect) -> Option<&ActiveEffect> {
+        self.active.get(&effect)
+    }
+
+    /// The amplifier of the effect, if the player has it.
+    pub fn amplifier(&self, effect: StatusEffect) -> Option<u8> {
+        self.get(effect).map(|active| active.amplifier)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&StatusEffect, &ActiveEffect)> {
+        self.active.iter()
+    }
+}
+
+/// Timed status effects on players, with the effect of each of them on health and speed. Damage
+/// effects are applied by `CombatPlugin`.
+pub struct StatusEffectsPlugin;
+
+impl Plugin for StatusEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<DeathMessage>().add_systems(
+            Update,
+            (
+                init_clients,
+                tick_effects,
+                clear_on_death,
+                sync_effects.after(tick_effects).after(clear_on_death),
+            ),
+        );
+    }
+}
+
+fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
+    for entity in clients.iter() {
+        commands.entity(entity).insert(StatusEffects::default());
+    }
+}
+
+fn tick_effects(mut players: Query<(&mut StatusEffects, &mut Health)>) {
+    for (mut effects, mut health) in players.iter_mut() {
+        if let Some(regeneration) = effects.get(StatusEffect::Regeneration) {
+            // Every 50 ticks at level I, twice as often for every level above
+            let interval = 50 >> regeneration.amplifier.min(5);
+            if regeneration.duration % interval == 0 && health.0 < MAX_HEALTH {
+                health.0 = (health.0 + 1.0).min(MAX_HEALTH);
+            }
+        }
+
+        let ended: Vec<StatusEffect> = effects
+            .active
+            .iter_mut()
+            .filter_map(|(effect, active)| {
+                active.duration -= 1;
+                (active.duration <= 0).then_some(*effect)
+            })
+            .collect();
+        for effect in ended {
+            effects.remove(effect);
+        }
+    }
+}
+
+fn clear_on_death(mut players: Query<&mut StatusEffects>, mut deaths: MessageReader<DeathMessage>) {
+    for DeathMessage(entity, _) in deaths.read() {
+        if let Ok(mut effects) = players.get_mut(*entity) {
+            effects.clear();
+        }
+    }
+}
+
+/// Sends changed effects to their player and applies what they do once when added or removed.
+fn sync_effects(
+    mut players: Query<(
+        &mut Client,
+        &mut StatusEffects,
+        &mut Absorption,
+        &mut EntityAttributes,
+    )>,
+) {
+    for (mut client, mut effects, mut absorption, mut attributes) in players.iter_mut() {
+        if effects.changed.is_empty() {
+            continue;
+        }
+        let effects = &mut *effects;
+
+        for (effect, removed) in effects.removed.drain(..) {
+            if effect == StatusEffect::Absorption {
+                let amount = 4.0 * (removed.amplifier as f32 + 1.0);
+                absorption.0 = (absorption.0 - amount).max(0.0);
+            }
+        }
+        for effect in effects.changed.drain() {
+            match effects.active.get(&effect) {
+                Some(active) => {
+                    if effect == StatusEffect::Absorption {
+                        absorption.0 = absorption.0.max(4.0 * (active.amplifier as f32 + 1.0));
+                    }
+                    client.write_packet(&EntityStatusEffectS2c {
+                        entity_id: VarInt(0),
+                        effect_id: VarInt(effect as i32),
+                        amplifier: active.amplifier,
+                        duration: VarInt(active.duration as i32),
+                        flags: Flags::new().with_show_particles(true).with_show_icon(true),
+                        factor_codec: None,
+                    });
+                }
+                None => {
+                    client.write_packet(&RemoveEntityStatusEffectS2c {
+                        entity_id: VarInt(0),
+                        effect_id: VarInt(effect as i32),
+                    });
+                }
+            }
+        }
+
+        // Both work on the total like vanilla's attribute modifiers, so they stack by multiplying
+        let speed = effects
+            .amplifier(StatusEffect::Speed)
+            .map_or(1.0, |amplifier| 1.0 + 0.2 * (amplifier as f64 + 1.0));
+        let slowness = effects
+            .amplifier(StatusEffect::Slowness)
+            .map_or(1.0, |amplifier| {
+                (1.0 - 0.15 * (amplifier as f64 + 1.0)).max(0.0)
+            });
+        attributes.set_base_value(
+            EntityAttribute::GenericMovementSpeed,
+            BASE_MOVEMENT_SPEED * speed * slowness,
+        );
+    }
+}
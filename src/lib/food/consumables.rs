@@ -0,0 +1,374 @@
+#![allow(clippy::type_complexity)]
+
+use super::hunger::{Hunger, MAX_FOOD};
+use crate::effects::{StatusEffect, StatusEffects, StatusEffectsPlugin};
+use crate::player::MAX_HEALTH;
+use chunkedge::client::Client;
+use chunkedge::entity::EntityStatus;
+use chunkedge::entity::living::Health;
+use chunkedge::event_loop::PacketMessage;
+use chunkedge::interact_item::InteractItemMessage;
+use chunkedge::inventory::player_inventory::PlayerInventory;
+use chunkedge::inventory::{HeldItem, Inventory, PlayerAction};
+use chunkedge::item::ItemComponent;
+use chunkedge::prelude::*;
+use chunkedge::protocol::packets::play::PlayerActionC2s;
+use chunkedge::{Hand, ItemKind, Server};
+use std::collections::HashMap;
+
+/// Vanilla's potion registry ids of the potions below
+const STRONG_SWIFTNESS: i32 = 15;
+const STRONG_HEALING: i32 = 25;
+
+/// An item that players can eat or drink
+#[derive(Clone, Copy)]
+pub struct Consumable {
+    pub item: ItemKind,
+    /// Ticks it takes to consume the item. Items that take 0 ticks are used up on right click.
+    pub use_ticks: i64,
+    /// Food points restored, if the player has hunger
+    pub food: i32,
+    /// Saturation restored, capped at the player's food level like in vanilla
+    pub saturation: f32,
+    /// Health restored right away
+    pub heal: f32,
+    /// Effects given as (effect, amplifier, ticks)
+    pub effects: &'static [(StatusEffect, u8, i64)],
+    /// Whether it can be consumed with a full food bar
+    pub always_edible: bool,
+    /// The potion a potion item has to contain, since every potion is the same item
+    pub potion: Option<i32>,
+    /// The item that is left behind, like the bowl of a soup
+    pub remainder: Option<ItemKind>,
+}
+
+pub const GOLDEN_APPLE: Consumable = Consumable {
+    item: ItemKind::GoldenApple,
+    use_ticks: 32,
+    food: 4,
+    saturation: 9.6,
+    heal: 0.0,
+    // Regeneration II for 5 seconds and Absorption I for 2 minutes, like vanilla
+    effects: &[
+        (StatusEffect::Regeneration, 1, 100),
+        (StatusEffect::Absorption, 0, 2400),
+    ],
+    always_edible: true,
+    potion: None,
+    remainder: None,
+};
+
+pub const STEAK: Consumable = Consumable {
+    item: ItemKind::CookedBeef,
+    use_ticks: 32,
+    food: 8,
+    saturation: 12.8,
+    heal: 0.0,
+    effects: &[],
+    always_edible: false,
+    potion: None,
+    remainder: None,
+};
+
+pub const BREAD: Consumable = Consumable {
+    item: ItemKind::Bread,
+    use_ticks: 32,
+    food: 5,
+    saturation: 6.0,
+    heal: 0.0,
+    effects: &[],
+    always_edible: false,
+    potion: None,
+    remainder: None,
+};
+
+pub const MUSHROOM_STEW: Consumable = Consumable {
+    item: ItemKind::MushroomStew,
+    use_ticks: 32,
+    food: 6,
+    saturation: 7.2,
+    heal: 0.0,
+    effects: &[],
+    always_edible: false,
+    potion: None,
+    remainder: Some(ItemKind::Bowl),
+};
+
+/// Mushroom stew for soup PvP, which heals 3.5 hearts as soon as it is right clicked
+pub const SOUP: Consumable = Consumable {
+    item: ItemKind::MushroomStew,
+    use_ticks: 0,
+    food: 0,
+    saturation: 0.0,
+    heal: 7.0,
+    effects: &[],
+    always_edible: true,
+    potion: None,
+    remainder: Some(ItemKind::Bowl),
+};
+
+/// A potion of Instant Health II
+pub const HEALING_POTION: Consumable = Consumable {
+    item: ItemKind::Potion,
+    use_ticks: 32,
+    food: 0,
+    saturation: 0.0,
+    heal: 8.0,
+    effects: &[],
+    always_edible: true,
+    potion: Some(STRONG_HEALING),
+    remainder: Some(ItemKind::GlassBottle),
+};
+
+pub const SPEED_POTION: Consumable = Consumable {
+    item: ItemKind::Potion,
+    use_ticks: 32,
+    food: 0,
+    saturation: 0.0,
+    heal: 0.0,
+    // Speed II for 1:30
+    effects: &[(StatusEffect::Speed, 1, 1800)],
+    always_edible: true,
+    potion: Some(STRONG_SWIFTNESS),
+    remainder: Some(ItemKind::GlassBottle),
+};
+
+/// Every consumable of the mode, by item and the potion it contains
+#[derive(Resource, Default)]
+pub struct Consumables(pub HashMap<(ItemKind, Option<i32>), Consumable>);
+
+impl Consumables {
+    /// The consumable `stack` is, if any.
+    pub fn get(&self, stack: &ItemStack) -> Option<&Consumable> {
+        self.0.get(&(stack.item, potion_id(stack)))
+    }
+}
+
+fn potion_id(stack: &ItemStack) -> Option<i32> {
+    stack.components().iter().find_map(|component| match component {
+        ItemComponent::PotionContents { potion_id, .. } => potion_id.map(|id| id.0),
+        _ => None,
+    })
+}
+
+/// Sent when a player finished consuming an item
+#[derive(Message)]
+pub struct ConsumedMessage {
+    pub player: Entity,
+    pub item: ItemKind,
+}
+
+/// Lets players consume the given items. Can be added more than once, e.g. by plugins that bring
+/// their own consumables.
+pub struct ConsumablesPlugin {
+    pub consumables: Vec<Consumable>,
+}
+
+impl Plugin for ConsumablesPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world().contains_resource::<Consumables>() {
+            if !app.is_plugin_added::<StatusEffectsPlugin>() {
+                app.add_plugins(StatusEffectsPlugin);
+            }
+            app.init_resource::<Consumables>()
+                .add_message::<ConsumedMessage>()
+                .add_systems(
+                    Update,
+                    (
+                        init_clients,
+                        set_use_tick,
+                        consume.after(set_use_tick),
+                        cancel_use,
+                    ),
+                );
+        }
+        let mut consumables = app.world_mut().resource_mut::<Consumables>();
+        for consumable in self.consumables.iter() {
+            consumables
+                .0
+                .insert((consumable.item, consumable.potion), *consumable);
+        }
+    }
+
+    fn is_unique(&self) -> bool {
+        false
+    }
+}
+
+/// When the player started using a consumable and with which hand. Nothing is being used while
+/// the tick is `i64::MAX`.
+#[derive(Component)]
+pub struct EatingStartTick(pub i64, pub Hand);
+
+fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
+    for entity in clients.iter() {
+        commands
+            .entity(entity)
+            .insert(EatingStartTick(i64::MAX, Hand::Main));
+    }
+}
+
+fn hand_slot(hand: Hand, held_item: &HeldItem) -> u16 {
+    match hand {
+        Hand::Main => held_item.slot(),
+        Hand::Off => PlayerInventory::SLOT_OFFHAND,
+    }
+}
+
+fn set_use_tick(
+    mut clients: Query<
+        (
+            Entity,
+            &mut Client,
+            &mut Inventory,
+            &HeldItem,
+            &mut EatingStartTick,
+            &mut Health,
+            &mut StatusEffects,
+            Option<&mut Hunger>,
+        ),
+        With<Client>,
+    >,
+    mut messages: MessageReader<InteractItemMessage>,
+    mut consumed: MessageWriter<ConsumedMessage>,
+    consumables: Res<Consumables>,
+    server: Res<Server>,
+) {
+    for message in messages.read() {
+        let Ok((
+            entity,
+            mut client,
+            mut inv,
+            held_item,
+            mut eat_tick,
+            mut health,
+            mut effects,
+            mut hunger,
+        )) = clients.get_mut(message.client)
+        else {
+            continue;
+        };
+        let slot = hand_slot(message.hand, held_item);
+        let Some(consumable) = consumables.get(inv.slot(slot)) else {
+            continue;
+        };
+        let full = hunger.as_ref().is_some_and(|hunger| hunger.food >= MAX_FOOD);
+        if full && !consumable.always_edible {
+            continue;
+        }
+
+        if consumable.use_ticks > 0 {
+            eat_tick.0 = server.current_tick();
+            eat_tick.1 = message.hand;
+        } else if consumable.heal == 0.0 || health.0 < MAX_HEALTH {
+            apply(
+                consumable,
+                &mut inv,
+                slot,
+                &mut health,
+                &mut effects,
+                hunger.as_deref_mut(),
+            );
+            client.trigger_status(EntityStatus::ConsumeItem);
+            consumed.write(ConsumedMessage {
+                player: entity,
+                item: consumable.item,
+            });
+        }
+    }
+}
+
+fn consume(
+    mut clients: Query<
+        (
+            Entity,
+            &mut Client,
+            &mut Inventory,
+            &HeldItem,
+            &mut EatingStartTick,
+            &mut Health,
+            &mut StatusEffects,
+            Option<&mut Hunger>,
+        ),
+        With<Client>,
+    >,
+    mut consumed: MessageWriter<ConsumedMessage>,
+    consumables: Res<Consumables>,
+    server: Res<Server>,
+) {
+    for (
+        entity,
+        mut client,
+        mut inv,
+        held_item,
+        mut eat_tick,
+        mut health,
+        mut effects,
+        mut hunger,
+    ) in clients.iter_mut()
+    {
+        if eat_tick.0 == i64::MAX {
+            continue;
+        }
+        let slot = hand_slot(eat_tick.1, held_item);
+        // Switching to another item stops eating
+        let Some(consumable) = consumables.get(inv.slot(slot)) else {
+            eat_tick.0 = i64::MAX;
+            continue;
+        };
+        if server.current_tick() - eat_tick.0 > consumable.use_ticks {
+            eat_tick.0 = i64::MAX;
+            client.trigger_status(EntityStatus::ConsumeItem);
+            apply(
+                consumable,
+                &mut inv,
+                slot,
+                &mut health,
+                &mut effects,
+                hunger.as_deref_mut(),
+            );
+            consumed.write(ConsumedMessage {
+                player: entity,
+                item: consumable.item,
+            });
+        }
+    }
+}
+
+fn apply(
+    consumable: &Consumable,
+    inv: &mut Inventory,
+    slot: u16,
+    health: &mut Health,
+    effects: &mut StatusEffects,
+    hunger: Option<&mut Hunger>,
+) {
+    health.0 = (health.0 + consumable.heal).min(MAX_HEALTH);
+    for (effect, amplifier, ticks) in consumable.effects {
+        effects.add(*effect, *amplifier, *ticks);
+    }
+    if let Some(hunger) = hunger {
+        hunger.food = (hunger.food + consumable.food).min(MAX_FOOD);
+        hunger.saturation = (hunger.saturation + consumable.saturation).min(hunger.food as f32);
+    }
+
+    let count = inv.slot(slot).count;
+    match consumable.remainder {
+        Some(remainder) if count <= 1 => inv.set_slot(slot, ItemStack::new(remainder, 1)),
+        _ => inv.set_slot_amount(slot, count - 1),
+    }
+}
+
+fn cancel_use(
+    mut clients: Query<&mut EatingStartTick, With<Client>>,
+    mut packets: MessageReader<PacketMessage>,
+) {
+    for packet in packets.read() {
+        if let Some(pkt) = packet.decode::<PlayerActionC2s>()
+            && pkt.action == PlayerAction::ReleaseUseItem
+            && let Ok(mut eat_tick) = clients.get_mut(packet.client)
+        {
+            eat_tick.0 = i64::MAX;
+        }
+    }
+}
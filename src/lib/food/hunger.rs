@@ -0,0 +1,195 @@
+use crate::death::DeathMessage;
+use crate::player::MAX_HEALTH;
+use chunkedge::client::Client;
+use chunkedge::entity::OnGround;
+use chunkedge::entity::living::Health;
+use chunkedge::math::Vec3Swizzles;
+use chunkedge::prelude::*;
+use chunkedge::protocol::packets::play::HealthUpdateS2c;
+use chunkedge::protocol::{VarInt, WritePacket};
+
+pub const MAX_FOOD: i32 = 20;
+/// Players can't sprint with this much food or less
+const SPRINT_FOOD: i32 = 6;
+/// Players heal naturally with at least this much food
+const REGENERATION_FOOD: i32 = 18;
+/// Ticks between every natural heal or starvation damage
+const FOOD_TICKS: i64 = 80;
+/// Exhaustion that takes away a point of saturation, or food once saturation is gone
+const EXHAUSTION_PER_POINT: f32 = 4.0;
+/// Exhaustion of every block sprinted
+const SPRINT_EXHAUSTION: f32 = 0.1;
+const JUMP_EXHAUSTION: f32 = 0.05;
+const SPRINT_JUMP_EXHAUSTION: f32 = 0.2;
+const ATTACK_EXHAUSTION: f32 = 0.1;
+const REGENERATION_EXHAUSTION: f32 = 6.0;
+
+/// A player's food bar, which works like vanilla's on normal difficulty
+#[derive(Component)]
+pub struct Hunger {
+    pub food: i32,
+    pub saturation: f32,
+    pub exhaustion: f32,
+    sprinting: bool,
+    was_on_ground: bool,
+    /// Ticks since the last natural heal or starvation damage
+    timer: i64,
+    /// Health, food and saturation the client was last sent
+    sent: Option<(f32, i32, f32)>,
+}
+
+impl Default for Hunger {
+    fn default() -> Self {
+        Self {
+            food: MAX_FOOD,
+            saturation: 5.0,
+            exhaustion: 0.0,
+            sprinting: false,
+            was_on_ground: true,
+            timer: 0,
+            sent: None,
+        }
+    }
+}
+
+impl Hunger {
+    pub fn can_sprint(&self) -> bool {
+        self.food > SPRINT_FOOD
+    }
+
+    pub fn exhaust(&mut self, amount: f32) {
+        self.exhaustion += amount;
+    }
+
+    /// Refills the food bar, e.g. when the player respawns.
+    pub fn reset(&mut self) {
+        *self = Self {
+            sent: self.sent,
+            ..Self::default()
+        };
+    }
+}
+
+/// Hunger and saturation for every player, with natural regeneration and starvation. Players are
+/// kept from sprinting once they are low on food.
+pub struct HungerPlugin;
+
+impl Plugin for HungerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<DeathMessage>()
+            .add_systems(EventLoopUpdate, track_actions)
+            .add_systems(
+                Update,
+                (
+                    init_clients,
+                    exhaust_movement,
+                    update_food.after(exhaust_movement),
+                    clear_on_death,
+                    send_food.after(update_food).after(clear_on_death),
+                ),
+            );
+    }
+}
+
+fn init_clients(clients: Query<Entity, Added<Client>>, mut commands: Commands) {
+    for entity in clients.iter() {
+        commands.entity(entity).insert(Hunger::default());
+    }
+}
+
+fn track_actions(
+    mut players: Query<&mut Hunger>,
+    mut sprinting: MessageReader<SprintMessage>,
+    mut interact_entity: MessageReader<InteractEntityMessage>,
+) {
+    for &SprintMessage { client, state } in sprinting.read() {
+        if let Ok(mut hunger) = players.get_mut(client) {
+            hunger.sprinting = state == SprintState::Start && hunger.can_sprint();
+        }
+    }
+    for message in interact_entity.read() {
+        if message.interact == EntityInteraction::Attack
+            && let Ok(mut hunger) = players.get_mut(message.client)
+        {
+            hunger.exhaust(ATTACK_EXHAUSTION);
+        }
+    }
+}
+
+fn exhaust_movement(mut players: Query<(&mut Hunger, &Position, &OldPosition, &OnGround)>) {
+    for (mut hunger, pos, old_pos, on_ground) in players.iter_mut() {
+        let movement = pos.0 - old_pos.get();
+        if hunger.sprinting {
+            hunger.exhaust(SPRINT_EXHAUSTION * movement.xz().length() as f32);
+        }
+        if hunger.was_on_ground && !on_ground.0 && movement.y > 0.0 {
+            let exhaustion = if hunger.sprinting {
+                SPRINT_JUMP_EXHAUSTION
+            } else {
+                JUMP_EXHAUSTION
+            };
+            hunger.exhaust(exhaustion);
+        }
+        hunger.was_on_ground = on_ground.0;
+    }
+}
+
+fn update_food(mut players: Query<(&mut Hunger, &mut Health)>) {
+    for (mut hunger, mut health) in players.iter_mut() {
+        if hunger.exhaustion >= EXHAUSTION_PER_POINT {
+            hunger.exhaustion -= EXHAUSTION_PER_POINT;
+            if hunger.saturation > 0.0 {
+                hunger.saturation = (hunger.saturation - 1.0).max(0.0);
+            } else {
+                hunger.food = (hunger.food - 1).max(0);
+            }
+        }
+        if !hunger.can_sprint() {
+            hunger.sprinting = false;
+        }
+
+        if hunger.food >= REGENERATION_FOOD && health.0 < MAX_HEALTH {
+            hunger.timer += 1;
+            if hunger.timer >= FOOD_TICKS {
+                health.0 = (health.0 + 1.0).min(MAX_HEALTH);
+                hunger.exhaust(REGENERATION_EXHAUSTION);
+                hunger.timer = 0;
+            }
+        } else if hunger.food <= 0 {
+            hunger.timer += 1;
+            if hunger.timer >= FOOD_TICKS {
+                // Starving stops at half a heart on normal difficulty
+                if health.0 > 1.0 {
+                    health.0 -= 1.0;
+                }
+                hunger.timer = 0;
+            }
+        } else {
+            hunger.timer = 0;
+        }
+    }
+}
+
+fn clear_on_death(mut players: Query<&mut Hunger>, mut deaths: MessageReader<DeathMessage>) {
+    for DeathMessage(entity, _) in deaths.read() {
+        if let Ok(mut hunger) = players.get_mut(*entity) {
+            hunger.reset();
+        }
+    }
+}
+
+/// Health is sent along with the food bar, since both share a packet.
+fn send_food(mut players: Query<(&mut Client, &mut Hunger, &Health)>) {
+    for (mut client, mut hunger, health) in players.iter_mut() {
+        let state = (health.0, hunger.food, hunger.saturation);
+        if hunger.sent == Some(state) {
+            continue;
+        }
+        hunger.sent = Some(state);
+        client.write_packet(&HealthUpdateS2c {
+            health: health.0,
+            food: VarInt(hunger.food),
+            food_saturation: hunger.saturation,
+        });
+    }
+}
@@ -1 +1,3 @@
+pub mod consumables;
 pub mod golden_apple;
+pub mod hunger;
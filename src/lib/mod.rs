@@ -1,9 +1,11 @@
 pub mod color;
+pub mod combat;
 pub mod config;
 pub mod damage;
 pub mod db;
 pub mod death;
 pub mod duels;
+pub mod effects;
 pub mod food;
 pub mod player;
 pub mod projectiles;
@@ -7,6 +7,9 @@ use chunkedge::{
     protocol::packets::play::PlayerActionC2s,
 };
 
+/// Health of a player with full hearts
+pub const MAX_HEALTH: f32 = 20.0;
+
 pub struct InteractionBroadcastPlugin;
 
 impl Plugin for InteractionBroadcastPlugin {
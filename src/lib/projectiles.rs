@@ -3,6 +3,7 @@
 
 use bevy_ecs::query::QueryData;
 use chunkedge::inventory::player_inventory::PlayerInventory;
+use chunkedge::math::Vec3Swizzles;
 use chunkedge::{
     entity::{Velocity, arrow::ArrowEntity},
     event_loop::PacketMessage,
@@ -18,9 +19,26 @@ use parry3d::{
     shape::Cuboid,
 };
 
+/// Arrows despawn this long after being shot, or after sticking in a block
+const ARROW_LIFETIME_TICKS: i64 = 1200;
+/// Distance between the points of an arrow's path that are checked for blocks
+const BLOCK_STEP: f64 = 0.25;
+/// How close players have to get to a stuck arrow to pick it up, horizontally and vertically
+const PICKUP_RANGE: (f64, f64) = (1.3, 2.3);
+
 #[derive(Component)]
 struct BowDrawTick(pub i64, pub Hand);
 
+/// State of a shot arrow
+#[derive(Component)]
+pub struct ArrowState {
+    /// The chunk layer the arrow collides with, which is the one its shooter saw
+    pub chunk_layer: Entity,
+    pub spawn_tick: i64,
+    /// When the arrow hit a block, after which it stays in place until it is picked up
+    pub stuck_tick: Option<i64>,
+}
+
 #[derive(Component)]
 pub struct ProjectileOwner(pub Entity);
 
@@ -30,13 +48,19 @@ pub struct ProjectileCollisionMessage {
     pub player: Entity,
 }
 
-pub struct ProjectilePlugin;
+pub struct ProjectilePlugin {
+    /// Whether players get arrows stuck in blocks back by walking over them
+    pub arrow_pickup: bool,
+}
 
 impl Plugin for ProjectilePlugin {
     fn build(&self, app: &mut App) {
         app.add_message::<ProjectileCollisionMessage>()
             .add_systems(EventLoopUpdate, (set_use_tick, handle_player_actions))
             .add_systems(Update, (init_clients, apply_arrow_physics, cleanup_arrows));
+        if self.arrow_pickup {
+            app.add_systems(Update, pickup_arrows.after(apply_arrow_physics));
+        }
     }
 }
 
@@ -79,6 +103,7 @@ struct ActionQuery {
     look: &'static Look,
     yaw: &'static HeadYaw,
     layer: &'static EntityLayerId,
+    chunk_layer: &'static VisibleChunkLayer,
     draw_tick: &'static mut BowDrawTick,
 }
 fn handle_player_actions(
@@ -152,9 +177,14 @@ fn handle_player_actions(
                     *player.layer,
                 ))
                 .id();
-            commands
-                .entity(arrow_id)
-                .insert(ProjectileOwner(player.entity));
+            commands.entity(arrow_id).insert((
+                ProjectileOwner(player.entity),
+                ArrowState {
+                    chunk_layer: player.chunk_layer.0,
+                    spawn_tick: server.current_tick(),
+                    stuck_tick: None,
+                },
+            ));
 
             player.draw_tick.0 = i64::MAX;
         }
@@ -162,13 +192,36 @@ fn handle_player_actions(
 }
 
 pub fn apply_arrow_physics(
-    mut arrows: Query<(Entity, &mut Position, &mut Velocity), With<ArrowEntity>>,
-    players: Query<(Entity, &Position, &Velocity), (With<Client>, Without<ArrowEntity>)>,
+    mut arrows: Query<
+        (
+            Entity,
+            &mut Position,
+            &mut Velocity,
+            &EntityLayerId,
+            &mut ArrowState,
+        ),
+        With<ArrowEntity>,
+    >,
+    players: Query<
+        (Entity, &Position, &Velocity, &EntityLayerId),
+        (With<Client>, Without<ArrowEntity>),
+    >,
+    layers: Query<&ChunkLayer>,
     mut collisions: MessageWriter<ProjectileCollisionMessage>,
     mut commands: Commands,
+    server: Res<Server>,
 ) {
-    for (entity, mut pos, mut vel) in arrows.iter_mut() {
-        pos.0 += vel.0 / 20.0;
+    for (entity, mut pos, mut vel, layer, mut state) in arrows.iter_mut() {
+        let chunk_layer = layers.get(state.chunk_layer).ok();
+        if state.stuck_tick.is_some() {
+            // Arrows fall once the block they are stuck in is gone
+            if chunk_layer.is_some_and(|chunk_layer| !is_solid(chunk_layer, pos.0)) {
+                state.stuck_tick = None;
+            }
+            continue;
+        }
+        let old_pos = pos.0;
+        let new_pos = pos.0 + vel.0 / 20.0;
 
         // Gravity
         vel.0.y -= 1.0;
@@ -176,21 +229,27 @@ pub fn apply_arrow_physics(
         // Air resistance
         vel.0 *= 0.99;
 
+        // The arrow stops at the first block in its way, so players behind it can't be hit
+        let block = chunk_layer.and_then(|chunk_layer| block_hit(chunk_layer, old_pos, new_pos));
+        pos.0 = block.unwrap_or(new_pos);
+
         // Check for collisions (Arrow's have a hitbox of 0.5x0.5x0.5 and players have a hitbox of 0.6x1.8x0.6)
+        // along the path of the arrow during this tick
         let arrow_shape = Cuboid::new(Vector::new(0.5, 0.5, 0.5));
         let arrow_iso = Isometry3::new(
-            Vector::new(pos.0.x as f32, pos.0.y as f32, pos.0.z as f32),
+            Vector::new(old_pos.x as f32, old_pos.y as f32, old_pos.z as f32),
             na::zero(),
         );
-        let arrow_vel = Vector::new(
-            vel.0.x as f32 / 100.0,
-            vel.0.y as f32 / 100.0,
-            vel.0.z as f32 / 100.0,
-        );
+        let path = pos.0 - old_pos;
+        let arrow_vel = Vector::new(path.x as f32, path.y as f32, path.z as f32);
 
         let player_shape = Cuboid::new(Vector::new(0.6, 0.9, 0.6));
 
-        for (player_entity, player_pos, player_vel) in players.iter() {
+        let mut hit_player = false;
+        for (player_entity, player_pos, player_vel, player_layer) in players.iter() {
+            if player_layer.0 != layer.0 {
+                continue;
+            }
             let player_iso = Isometry3::new(
                 Vector::new(
                     player_pos.0.x as f32,
@@ -200,9 +259,9 @@ pub fn apply_arrow_physics(
                 na::zero(),
             );
             let player_vel = Vector::new(
-                player_vel.0.x as f32 / 100.0,
-                player_vel.0.y as f32 / 100.0,
-                player_vel.0.z as f32 / 100.0,
+                player_vel.0.x as f32 / 20.0,
+                player_vel.0.y as f32 / 20.0,
+                player_vel.0.z as f32 / 20.0,
             );
 
             if cast_shapes(
@@ -222,14 +281,88 @@ pub fn apply_arrow_physics(
                     arrow: entity,
                     player: player_entity,
                 });
+                hit_player = true;
+                break;
+            }
+        }
+
+        if !hit_player && block.is_some() {
+            vel.0 = DVec3::ZERO;
+            state.stuck_tick = Some(server.current_tick());
+        }
+    }
+}
+
+/// The first point on the way from `from` to `to` that is inside a solid block.
+fn block_hit(layer: &ChunkLayer, from: DVec3, to: DVec3) -> Option<DVec3> {
+    let steps = (from.distance(to) / BLOCK_STEP).ceil().max(1.0) as usize;
+    (1..=steps)
+        .map(|step| from.lerp(to, step as f64 / steps as f64))
+        .find(|point| is_solid(layer, *point))
+}
+
+fn is_solid(layer: &ChunkLayer, point: DVec3) -> bool {
+    layer
+        .block(BlockPos::from(point.floor()))
+        .is_some_and(|block| block.state.collision_shapes().next().is_some())
+}
+
+fn pickup_arrows(
+    mut players: Query<
+        (
+            &mut Client,
+            &mut Inventory,
+            &Position,
+            &EntityLayerId,
+            &GameMode,
+        ),
+        Without<ArrowEntity>,
+    >,
+    arrows: Query<(Entity, &Position, &EntityLayerId, &ArrowState), With<ArrowEntity>>,
+    mut commands: Commands,
+) {
+    for (entity, arrow_pos, arrow_layer, state) in arrows.iter() {
+        if state.stuck_tick.is_none() {
+            continue;
+        }
+        for (mut client, mut inv, pos, layer, gamemode) in players.iter_mut() {
+            let offset = arrow_pos.0 - pos.0;
+            if layer.0 != arrow_layer.0
+                || *gamemode == GameMode::Spectator
+                || offset.xz().length() > PICKUP_RANGE.0
+                || offset.y.abs() > PICKUP_RANGE.1
+            {
+                continue;
             }
+            if let Some(slot) = inv.first_slot_with_item(ItemKind::Arrow, 64) {
+                let count = inv.slot(slot).count + 1;
+                inv.set_slot_amount(slot, count);
+            } else if let Some(slot) = inv.first_empty_slot_in(9..45) {
+                inv.set_slot(slot, ItemStack::new(ItemKind::Arrow, 1));
+            } else {
+                continue;
+            }
+            client.play_sound(
+                Sound::EntityItemPickup,
+                SoundCategory::Player,
+                pos.0,
+                0.2,
+                1.0,
+            );
+            commands.entity(entity).insert(Despawned);
+            break;
         }
     }
 }
 
-fn cleanup_arrows(arrows: Query<(Entity, &Position), With<ArrowEntity>>, mut commands: Commands) {
-    for (entity, pos) in arrows.iter() {
-        if pos.0.y < -50.0 {
+fn cleanup_arrows(
+    arrows: Query<(Entity, &Position, &ArrowState), With<ArrowEntity>>,
+    mut commands: Commands,
+    server: Res<Server>,
+) {
+    for (entity, pos, state) in arrows.iter() {
+        let since = state.stuck_tick.unwrap_or(state.spawn_tick);
+        if pos.0.y < -50.0 || server.current_tick() - since > ARROW_LIFETIME_TICKS {
             commands.entity(entity).insert(Despawned);
         }
     }
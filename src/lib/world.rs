@@ -3,6 +3,41 @@
 use chunkedge::interact_block::InteractBlockMessage;
 use chunkedge::inventory::player_inventory::PlayerInventory;
 use chunkedge::{inventory::HeldItem, math::IVec3, prelude::*};
+use std::collections::HashMap;
+
+/// Remembers the original state of every block changed in a chunk layer, so the layer can be
+/// reset without reloading it. Block changes made by the digging and placing plugins are
+/// recorded automatically on layers with this component.
+#[derive(Component, Default)]
+pub struct ChangedBlocks(pub HashMap<BlockPos, BlockState>);
+
+impl ChangedBlocks {
+    /// Remembers the current state of the block at `pos` if it hasn't been changed yet.
+    pub fn record(&mut self, layer: &ChunkLayer, pos: BlockPos) {
+        if let Some(block) = layer.block(pos) {
+            self.0.entry(pos).or_insert(block.state);
+        }
+    }
+
+    /// Sets a block, recording its original state.
+    pub fn set_block(
+        &mut self,
+        layer: &mut ChunkLayer,
+        pos: impl Into<BlockPos>,
+        state: BlockState,
+    ) {
+        let pos = pos.into();
+        self.record(layer, pos);
+        layer.set_block(pos, state);
+    }
+
+    /// Puts every changed block back to its original state.
+    pub fn rollback(&mut self, layer: &mut ChunkLayer) {
+        for (pos, state) in self.0.drain() {
+            layer.set_block(pos, state);
+        }
+    }
+}
 
 #[derive(Message)]
 pub struct BlockBreakMessage {
@@ -32,7 +67,7 @@ impl Plugin for DiggingPlugin {
 
 fn handle_digging_events(
     mut clients: Query<(&GameMode, &mut Inventory, &VisibleChunkLayer)>,
-    mut layers: Query<&mut ChunkLayer>,
+    mut layers: Query<(&mut ChunkLayer, Option<&mut ChangedBlocks>)>,
     mut messages: MessageReader<DiggingMessage>,
     mut break_messages: MessageWriter<BlockBreakMessage>,
     res: Res<DiggingPluginResource>,
@@ -45,7 +80,7 @@ fn handle_digging_events(
             {
                 continue;
             }
-            let Ok(mut chunk_layer) = layers.get_mut(layer.0) else {
+            let Ok((mut chunk_layer, mut changes)) = layers.get_mut(layer.0) else {
                 continue;
             };
             let Some(block) = chunk_layer.block(message.position) else {
@@ -82,8 +117,14 @@ fn handle_digging_events(
                         _ => continue,
                     };
                     let other_pos = message.position.get_in_direction(dir);
+                    if let Some(changes) = changes.as_mut() {
+                        changes.record(&chunk_layer, other_pos);
+                    }
                     chunk_layer.set_block(other_pos, BlockState::AIR);
                 }
+                if let Some(changes) = changes.as_mut() {
+                    changes.record(&chunk_layer, message.position);
+                }
                 chunk_layer.set_block(message.position, BlockState::AIR);
                 break_messages.write(BlockBreakMessage {
                     client: message.client,
@@ -155,7 +196,7 @@ fn handle_placing_events(
         &HeldItem,
         &VisibleChunkLayer,
     )>,
-    mut layers: Query<&mut ChunkLayer>,
+    mut layers: Query<(&mut ChunkLayer, Option<&mut ChangedBlocks>)>,
     mut messages: MessageReader<InteractBlockMessage>,
     mut placing_messages: MessageWriter<BlockPlaceMessage>,
     restrictions: Option<Res<PlacingRestrictions>>,
@@ -193,7 +234,7 @@ fn handle_placing_events(
                     }
                 }
             }
-            let Ok(mut chunk_layer) = layers.get_mut(layer.0) else {
+            let Ok((mut chunk_layer, mut changes)) = layers.get_mut(layer.0) else {
                 inv.changed |= u64::MAX;
                 continue;
             };
@@ -220,6 +261,9 @@ fn handle_placing_events(
                         continue;
                     };
                     if block.state.is_replaceable() {
+                        if let Some(changes) = changes.as_mut() {
+                            changes.record(&chunk_layer, block_pos);
+                        }
                         chunk_layer.set_block(block_pos, block_kind.to_state());
                         let count = inv.slot(slot).count - 1;
                         inv.set_slot_amount(slot, count);